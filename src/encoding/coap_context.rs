@@ -6,16 +6,22 @@ use crate::{
     sys::console,
     encoding::{
         //json,                   //  Mynewt JSON encoding library
-        tinycbor::CborEncoder,  //  Mynewt CBOR encoding library
+        tinycbor,               //  Mynewt CBOR encoding library
+        tinycbor::CborEncoder,
     },
     libs::mynewt_rust,          //  JSON encoding helper library
     //libs::sensor_coap,
     hw::sensor::SensorValueType,
-    fill_zero, Strn, StrnRep,
+    result::*,
+    util::zeroable::{ zeroed, Zeroable },
+    Strn, StrnRep,
 };
 
+/// Error codes for COAP encoding failure. Moved to `mynewt::result`.
+pub use crate::result::CoapError;
+
 /// Global instance that contains the current state of the CoAP encoder. Only 1 encoding task is supported at a time.
-pub static mut COAP_CONTEXT: CoapContext = fill_zero!(CoapContext);
+pub static mut COAP_CONTEXT: CoapContext = zeroed();
 
 /// CoAP encoder state. Buffers the next key and value to be encoded.
 #[derive(Default)]
@@ -24,16 +30,23 @@ pub struct CoapContext {
     key_buffer: [u8; COAP_KEY_SIZE],
     /// Static buffer for the string value to be encoded. Will be passed to Mynewt COAP encoder API.  Always null-terminated.
     value_buffer: [u8; COAP_VALUE_SIZE],
+    /// First CBOR error since the last `check()`, or `CborNoError`
+    cbor_error: tinycbor::CborError,
+    /// First CoAP error since the last `check()`, or `CoapError::OK`
+    coap_error: CoapError,
 }
 
+/// `CoapContext` contains only byte arrays and error codes that are 0 when there is no error, so it's valid when all bytes are 0
+unsafe impl Zeroable for CoapContext {}
+
 /// Size of the static key buffer
 const COAP_KEY_SIZE: usize = 32;
 /// Size of the static value buffer
 const COAP_VALUE_SIZE: usize = 32;
 
 /// Global CBOR root map for encoding CBOR documents
-static mut cbor_encoder0: CborEncoder = fill_zero!(CborEncoder);
-static mut cbor_encoder1: CborEncoder = fill_zero!(CborEncoder);
+static mut cbor_encoder0: CborEncoder = zeroed();
+static mut cbor_encoder1: CborEncoder = zeroed();
 
 impl CoapContext {
 
@@ -170,14 +183,28 @@ impl CoapContext {
         }        
     }
 
-    /// Fail the encoding with an error if `res` is non-zero.
-    pub fn check_result(&self, res: u32) {
-        assert_eq!(res, 0, "enc fail");
+    /// Return the first encoding error since the last `check()` and clear it. Called by `coap!()` after encoding
+    /// the payload, so that `?` returns encoder errors like `CborErrorOutOfMemory` to the caller.
+    pub fn check(&mut self) -> MynewtResult<()> {
+        let cbor_error = core::mem::replace(&mut self.cbor_error, tinycbor::CborError_CborNoError);
+        let coap_error = core::mem::replace(&mut self.coap_error, CoapError::OK);
+        CborError::check(cbor_error) ? ;
+        if coap_error != CoapError::OK { return Err(MynewtError::Coap(coap_error)); }
+        Ok(())
     }
 
-    /// Fail the encoding with an error
+    /// Record the CBOR error if `res` is non-zero, to be returned by `check()`. Only the first error is kept.
+    pub fn check_result(&mut self, res: tinycbor::CborError) {
+        if self.cbor_error == tinycbor::CborError_CborNoError {
+            self.cbor_error = res;
+        }
+    }
+
+    /// Fail the encoding with an error, to be returned by `check()`. Only the first error is kept.
     pub fn fail(&mut self, err: CoapError) {
-        assert_eq!(err, CoapError::OK, "enc fail");
+        if self.coap_error == CoapError::OK {
+            self.coap_error = err;
+        }
     }
 
     /// Cast itself as a `*mut c_void`
@@ -187,23 +214,6 @@ impl CoapContext {
     }
 }
 
-/// Error codes for COAP encoding failure
-#[derive(PartialEq)]
-pub enum CoapError {
-    /// No error
-    OK = 0,
-    /// Encoded value is not unsigned integer
-    VALUE_NOT_UINT = 1,
-}
-
-/// Implement formatted output for CoapError
-impl ::core::fmt::Debug for CoapError {
-    fn fmt(&self, _fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
-        //  TODO
-        Ok(())
-    }
-}
-
 /// Convert the type to array of bytes that may or may not end with null
 pub trait ToBytesOptionalNull {
     /// Convert the type to array of bytes that may or may not end with null
@@ -246,11 +256,7 @@ impl ToBytesOptionalNull for CStr {
 impl<'a> ToBytesOptionalNull for crate::Strn<'a> {
     /// Convert the type to array of bytes that may or may not end with null. Strn always ends with null.
     fn to_bytes_optional_nul(&self) -> &[u8] {
-        match self.rep {
-            StrnRep::ByteStr(bs) => { bs }
-            StrnRep::CStr(_cstr)  => { assert!(false, "strn bytes"); &[] }
-            //  TODO: unsafe { ::core::mem::transmute::<*const u8, &[u8]>(cstr) }
-        }
+        self.as_bytes_with_nul()
     }
 }
 
@@ -477,6 +477,8 @@ macro_rules! coap_root {
     $crate::oc_rep_start_root_object!($context);
     $children0;
     $crate::oc_rep_end_root_object!($context);
+    //  Return the first encoding error, e.g. encoder buffer full.
+    unsafe { mynewt::encoding::coap_context::COAP_CONTEXT.check() ? ; }
     d!(end cbor coap_root);
   }};
 
@@ -487,6 +489,8 @@ macro_rules! coap_root {
     unsafe { mynewt::libs::sensor_coap::json_rep_start_root_object(); }
     $children0;
     unsafe { mynewt::libs::sensor_coap::json_rep_end_root_object(); }
+    //  Return the first encoding error, e.g. value not uint.
+    unsafe { mynewt::encoding::coap_context::COAP_CONTEXT.check() ? ; }
     d!(end json coap_root);
   }};
 }
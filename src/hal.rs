@@ -1,11 +1,11 @@
-use crate::{hw::hal, kernel::os, result::*};
+use crate::{hw::hal, kernel::time::{self, Duration}, result::*};
 use embedded_hal;
 
 /// Rust Embedded HAL interface for Mynewt I2C
 impl I2C {
-    /// Create a new I2C port
+    /// Create a new I2C port with the default operation timeout `I2C_DEFAULT_TIMEOUT`
     pub fn new() -> Self {
-        I2C { i2c_num: 0, timeout: 1000 }
+        I2C { i2c_num: 0, timeout: I2C_DEFAULT_TIMEOUT.to_ticks_ceil() }
     }
 
     /// Initiaise the I2C port
@@ -13,14 +13,14 @@ impl I2C {
         &mut self,
         i2c_num: u8,
         i2c_settings: *const hal::hal_i2c_settings,
-        operation_timeout_in_ticks: u32,
+        operation_timeout: Duration,
     ) -> MynewtResult<()> {
         let rc = unsafe { hal::hal_i2c_config(i2c_num, i2c_settings) };
         check_i2c_return_code(rc)?;
         let rc = unsafe { hal::hal_i2c_enable(i2c_num) };
         check_i2c_return_code(rc)?;
         self.i2c_num = i2c_num;
-        self.timeout = operation_timeout_in_ticks;
+        self.timeout = operation_timeout.to_ticks_ceil();
         Ok(())
     }
 }
@@ -77,16 +77,9 @@ impl embedded_hal::blocking::i2c::WriteRead for I2C {
 }
 
 fn check_i2c_return_code(rc: i32) -> crate::result::MynewtResult<()> {
-    type E=crate::result::MynewtError;
-    match rc as u32 {
-        0=> Ok(()),
-        hal::HAL_I2C_ERR_UNKNOWN => Err(E::HAL_I2C_ERR_UNKNOWN),
-        hal::HAL_I2C_ERR_INVAL => Err(E::HAL_I2C_ERR_INVAL),
-        hal::HAL_I2C_ERR_TIMEOUT => Err(E::HAL_I2C_ERR_TIMEOUT),
-        hal::HAL_I2C_ERR_ADDR_NACK => Err(E::HAL_I2C_ERR_ADDR_NACK),
-        hal::HAL_I2C_ERR_DATA_NACK => Err(E::HAL_I2C_ERR_DATA_NACK),
-        _ => Err(E::HAL_I2C_ERR_UNKNOWN),
-    }
+    //  `HAL_I2C_ERR_*` codes are converted to the I2C errors, other codes are kept as `Other(rc)`.
+    if rc == 0 { Ok(()) }
+    else { Err(crate::result::MynewtError::from(rc)) }
 }
 
 /// Rust Embedded HAL interface for Mynewt SPI
@@ -104,19 +97,19 @@ impl SPI {
         -> MynewtResult<()> {
         //  Disable the SPI port in case External SPI Flash driver has already enabled it.
         let rc = unsafe { hal::hal_spi_disable(spi_num) };
-        assert_eq!(rc, 0, "spi disable fail");
+        HalError::check(rc, HalError::SpiDisable) ? ;
 
         //  Configure the SPI port.
         let rc = unsafe { hal::hal_spi_config(spi_num, spi_settings) };
-        assert_eq!(rc, 0, "spi config fail");
+        HalError::check(rc, HalError::SpiConfig) ? ;
 
         //  Enable the SPI port.
         let rc = unsafe { hal::hal_spi_enable(spi_num) };
-        assert_eq!(rc, 0, "spi enable fail");
+        HalError::check(rc, HalError::SpiEnable) ? ;
 
         //  Set the CS Pin to low only when transmitting.
         let rc = unsafe { hal::hal_gpio_init_out(cs_pin, 1) };
-        assert_eq!(rc, 0, "spi init fail");
+        HalError::check(rc, HalError::GpioInit) ? ;
         self.spi_num = spi_num;
         self.cs_pin  = cs_pin;
         Ok(())
@@ -130,13 +123,13 @@ impl embedded_hal::blocking::spi::Write<u8> for SPI {
         //  Select the device
         unsafe { hal::hal_gpio_write(self.cs_pin, 0) };
         //  Send the data
-        unsafe { hal::hal_spi_txrx(self.spi_num, 
+        let rc = unsafe { hal::hal_spi_txrx(self.spi_num, 
             core::mem::transmute(words.as_ptr()),  //  TX Buffer
             core::ptr::null_mut(),                 //  RX Buffer (don't receive)
             words.len() as i32) };                 //  Length
         //  De-select the device
         unsafe { hal::hal_gpio_write(self.cs_pin, 1) };
-        Ok(())
+        HalError::check(rc, HalError::SpiTransfer)
     }
 
     /// Reuse Mynewt error codes
@@ -157,7 +150,7 @@ impl GPIO {
         //  TODO: let dc = pins.d0.into_push_pull_output(&mut pins.port);
         //  TODO: let rst = pins.d1.into_push_pull_output(&mut pins.port);
         let rc = unsafe { hal::hal_gpio_init_out(pin, 0) };
-        assert_eq!(rc, 0, "gpio fail");
+        HalError::check(rc, HalError::GpioInit) ? ;
         self.pin = pin;
         Ok(())
     }
@@ -194,10 +187,7 @@ impl Delay {
 impl embedded_hal::blocking::delay::DelayMs<u8> for Delay {
     /// Sleep for the specified number of milliseconds
     fn delay_ms(&mut self, ms: u8) {
-        //  TODO: Get this constant from Mynewt
-        const OS_TICKS_PER_SEC: u32 = 128;
-        let delay_ticks = (ms as u32) * OS_TICKS_PER_SEC / 1000;
-        unsafe { os::os_time_delay(delay_ticks) };
+        time::delay(Duration::from_millis(ms as u32));
     }
 }
 
@@ -209,11 +199,14 @@ pub struct SPI {
     cs_pin: i32,
 }
 
+/// Default timeout for each I2C operation
+const I2C_DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Rust Embedded HAL interface for Mynewt I2C
 pub struct I2C {
     /// Mynewt I2C port number
     i2c_num: u8,
-    /// Operation timeout in ticks
+    /// Operation timeout in ticks, rounded up from the `Duration`
     timeout: u32,
 }
 
@@ -8,10 +8,15 @@ use mynewt_macros::{
 use crate as mynewt;
 use crate::{
     result::*,
-    kernel::os::*,
+    kernel::{
+        os::*,
+        critical::critical_section,
+        device::DeviceRef,
+        event::EventQueue,
+    },
     Ptr,
     Strn,
-    fill_zero,
+    util::zeroable::{ zeroed, Zeroable },
 };
 
 /// Contains the auto-generated Rust bindings for the Mynewt Sensor API
@@ -20,6 +25,14 @@ mod bindings;  //  Import `bindings.rs` containing the bindings
 /// Export all bindings. TODO: Export only the API bindings.
 pub use self::bindings::*;
 
+/// Async sensor reads in the sensor manager task
+#[cfg(feature = "async")]
+mod async_read;  //  Import `async_read.rs` containing the async sensor reads
+
+/// Export the async sensor reads
+#[cfg(feature = "async")]
+pub use self::async_read::{ read_async, SensorRead };
+
 ///  Convert the sensor data received from Mynewt into a `SensorValue` for transmission, which includes the sensor data key. 
 ///  `sensor_type` indicates the type of data in `sensor_data`.
 #[allow(non_snake_case, unused_variables)]
@@ -31,7 +44,7 @@ fn convert_sensor_data<'a>(sensor_data: sensor_data_ptr, sensor_key: &'static St
         value: match sensor_type {
             SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW => {  //  If this is raw temperature...
                 //  Interpret the sensor data as a `sensor_temp_raw_data` struct that contains raw temp.
-                let mut rawtempdata = fill_zero!(sensor_temp_raw_data);
+                let mut rawtempdata = zeroed::<sensor_temp_raw_data>();
                 let rc = unsafe { get_temp_raw_data(sensor_data, &mut rawtempdata) };
                 assert_eq!(rc, 0, "rawtmp fail");
                 //  Check that the raw temperature data is valid.
@@ -42,7 +55,7 @@ fn convert_sensor_data<'a>(sensor_data: sensor_data_ptr, sensor_key: &'static St
             #[cfg(feature = "use_float")]  //  If floating-point is enabled...
             SENSOR_TYPE_GEOLOCATION => {   //  If sensor data is GPS geolocation...
                 //  Interpret the sensor data as a `sensor_geolocation_data` struct that contains GPS geolocation.
-                let mut geolocation = fill_zero!(sensor_geolocation_data);
+                let mut geolocation = zeroed::<sensor_geolocation_data>();
                 let rc = unsafe { get_geolocation_data(sensor_data, &mut geolocation) };
                 assert_eq!(rc, 0, "geodata fail");
                 //  Check that the geolocation data is valid.
@@ -87,19 +100,60 @@ pub fn register_listener(sensor: *mut sensor, listener: sensor_listener) -> Myne
     if arg < MAX_SENSOR_LISTENERS {
         //  Found the Wrapped Sensor Listener. Register the associated Sensor Listener with Mynewt.
         //  Pass the associated listener to the unsafe Mynewt API.
-        unsafe { sensor_register_listener(sensor, &mut SENSOR_LISTENERS[arg].listener) };
+        let rc = unsafe { sensor_register_listener(sensor, &mut SENSOR_LISTENERS[arg].listener) };
+        if rc != 0 { return Err(MynewtError::from(rc)).context("sensor register listener"); }
     } else {
         //  TODO: Allocate a Wrapped Sensor Listener.
         //  If not found, copy the listener and register the copied Sensor Listener with Mynewt.
-        unsafe { assert_eq!(LISTENER_INTERNAL.sl_sensor_type, 0, "reg lis") };  //  Make sure it's not used.
+        if unsafe { LISTENER_INTERNAL.sl_sensor_type } != 0 {  //  Make sure it's not used.
+            return Err(SensorError::ListenerInUse.into()).context("sensor register listener");
+        }
         //  Copy the caller's listener to the internal listener.
         unsafe { LISTENER_INTERNAL = listener };
         //  Pass the internal listener to the unsafe Mynewt API.
-        unsafe { sensor_register_listener(sensor, &mut LISTENER_INTERNAL) };
+        let rc = unsafe { sensor_register_listener(sensor, &mut LISTENER_INTERNAL) };
+        if rc != 0 { return Err(MynewtError::from(rc)).context("sensor register listener"); }
     }
     Ok(())
 }
 
+///  Designate the event queue that will process sensor manager events, e.g. polling the sensors.
+///  By default the sensor manager uses the default event queue processed by the OS main task.
+///  Equivalent to `sensor_mgr_evq_set()`, which is not exported by Mynewt.
+///  Call this before the sensors are polled, e.g. before `set_poll_rate_ms()`. A pending wakeup is moved
+///  to the new queue, but sensor events already queued on the old queue are still processed there.
+pub fn set_manager_queue(queue: &'static EventQueue) {
+    let evq = queue.as_ptr();
+    //  Sensor manager may be polling the sensors, so we lock out interrupts while switching queues.
+    critical_section(|_cs| {
+        let callout = unsafe { &mut *core::ptr::addr_of_mut!(sensor_mgr.mgr_wakeup_callout) };
+        //  Remember if the wakeup is waiting to expire, or has expired and is queued on the old queue.
+        let armed  = !callout.c_next.tqe_prev.is_null();
+        let queued = callout.c_ev.ev_queued != 0;
+        let ticks  = if armed { unsafe { os_callout_remaining_ticks(callout, os_time_get()) } } else { 0 };
+        //  Stop the wakeup callout, which also removes its event from the old queue.
+        unsafe { os_callout_stop(callout) };
+        unsafe { sensor_mgr.mgr_eventq = evq };
+        callout.c_evq = evq;  //  Wakeup callout posts to the new queue
+        //  Re-arm the wakeup on the new queue.
+        if queued     { unsafe { os_eventq_put(evq, &mut callout.c_ev) }; }
+        else if armed { unsafe { os_callout_reset(callout, ticks) }; }
+    });
+}
+
+///  Return the event queue that processes sensor manager events
+pub fn manager_queue() -> &'static EventQueue {
+    let evq = unsafe { sensor_mgr_evq_get() };
+    assert!(!evq.is_null(), "no sensor eventq");
+    unsafe { EventQueue::from_ptr(evq) }
+}
+
+///  Return the Mynewt device for the sensor, or `None` if the sensor is null
+pub fn get_device(sensor: sensor_ptr) -> Option<DeviceRef> {
+    if unsafe { is_null_sensor(sensor) } { return None; }
+    unsafe { DeviceRef::from_ptr(sensor_get_device(sensor)) }
+}
+
 ///  Wrapped version of `sensor_data_func` used by Visual Embedded Rust
 pub type SensorValueFunc = fn(sensor_value: &SensorValue) -> MynewtResult<()>;
 
@@ -119,13 +173,15 @@ pub fn new_sensor_listener(
             break;
         }
     }
-    assert!(arg < MAX_SENSOR_LISTENERS, "increase MAX_SENSOR_LISTENERS");  //  Too many listeners registered. Increase MAX_SENSOR_LISTENERS
+    if arg >= MAX_SENSOR_LISTENERS {  //  Too many listeners registered. Increase MAX_SENSOR_LISTENERS
+        return Err(SensorError::TooManyListeners.into());
+    }
     //  Create a Mynewt `sensor_listener` that wraps the allocated `sensor_listener_info`
     let listener = sensor_listener {
         sl_sensor_type: sensor_type,
         sl_func:        Some(wrap_sensor_listener),
         sl_arg:         arg as *mut c_void,
-        ..fill_zero!(sensor_listener)
+        ..zeroed()
     };
     //  Allocate the `sensor_listener_info`
     unsafe { SENSOR_LISTENERS[arg] = sensor_listener_info {
@@ -165,8 +221,8 @@ extern "C" fn wrap_sensor_listener(
     let res = (info.listener_func)(&sensor_value);
 
     //  Check the result returned by the unwrapped listener function
-    if let Err(_err) = res {
-        SYS_EINVAL  //  Return error to Mynewt
+    if let Err(err) = res {
+        err.into()  //  Return error code to Mynewt
     } else {
         0           //  Return 0 to Mynewt to indicate no error
     }
@@ -190,7 +246,7 @@ static mut SENSOR_LISTENERS: [sensor_listener_info; MAX_SENSOR_LISTENERS] = [
         listener_func:  null_sensor_value_func,
         listener:       sensor_listener {  
             sl_func: Some(null_sensor_data_func),
-            ..fill_zero!(sensor_listener)
+            ..zeroed()
         },
     }; MAX_SENSOR_LISTENERS
 ];
@@ -200,7 +256,7 @@ static mut SENSOR_LISTENERS: [sensor_listener_info; MAX_SENSOR_LISTENERS] = [
 ///  Must be static so it won't go out of scope.  Must be mutable so that Rust won't move it while Mynewt is using it.
 static mut LISTENER_INTERNAL: sensor_listener = sensor_listener {  
     sl_func: Some(null_sensor_data_func),
-    ..fill_zero!(sensor_listener)
+    ..zeroed()
 };
 
 ///  Define a default sensor data function in case there is none.
@@ -330,20 +386,28 @@ pub struct sensor_geolocation_data {
     pub sgd_altitude_is_valid: u8, 
 }
 
+//  Sensor structs that are valid when all bytes are 0. `sensor_listener_info` contains a reference and a
+//  function pointer, so it must not be zero-filled.
+unsafe impl Zeroable for sensor_temp_raw_data {}
+unsafe impl Zeroable for sensor_geolocation_data {}
+unsafe impl Zeroable for sensor_listener {}
+unsafe impl Zeroable for sensor_listener__bindgen_ty_1 {}
+
 /// Points to a `sensor`.  Needed because `sensor` also refers to a namespace.
 pub type sensor_ptr = *mut sensor;
 /// Points to sensor arg passed by Mynewt to sensor listener
 pub type sensor_arg = *mut c_void;
 /// Points to sensor data passed by Mynewt to sensor listener
 pub type sensor_data_ptr = *mut c_void;
-/// Sensor data function that returns `MynewtError` instead of `i32`
+/// Sensor data function that returns the error code from `MynewtError::code()`.
+/// `MynewtError` itself is not FFI-safe, because unknown codes are kept in `MynewtError::Other`.
 pub type sensor_data_func =
     unsafe extern "C" fn(
         sensor: sensor_ptr,
         arg:    sensor_arg,
         data:   sensor_data_ptr,
         stype:  sensor_type_t,
-    ) -> MynewtError;
+    ) -> i32;
 /// Sensor data function that returns `i32` instead of `MynewtError`
 pub type sensor_data_func_untyped =
     unsafe extern "C" fn(
@@ -355,12 +419,7 @@ pub type sensor_data_func_untyped =
 
 /// Cast sensor data function from typed to untyped
 pub fn as_untyped(typed: sensor_data_func) -> Option<sensor_data_func_untyped> {
-    let untyped = unsafe { 
-        ::core::mem::transmute::
-            <sensor_data_func, sensor_data_func_untyped>
-            (typed)
-    };
-    Some(untyped)
+    Some(typed)
 }
 
 ///  Implement Copy for `sensor_listener`, because the `SENSOR_LISTENERS` initialiser will copy `sensor_listener` structs
@@ -0,0 +1,154 @@
+//! Async sensor reads, enabled by the `async` feature. The sensor is read by the sensor manager task,
+//! so the async task is not blocked while the driver talks to the sensor:
+//! ```
+//! let value = sensor::read_async(sensor, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, &TEMP_KEY, Duration::from_secs(1)).await ? ;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomPinned,
+    pin::Pin,
+    sync::atomic::{ AtomicU8, Ordering },
+    task::{ Context, Poll },
+};
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+        executor::WakerList,
+        time::{ self, Duration },
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr, Strn,
+};
+use super::{
+    convert_sensor_data, manager_queue,
+    sensor_arg, sensor_data_ptr, sensor_ptr, sensor_type_t,
+    SensorValue,
+};
+
+/// Read has not been requested
+const IDLE: u8 = 0;
+/// Read event has been posted to the sensor manager, which has not completed the read
+const QUEUED: u8 = 1;
+/// Read has completed
+const DONE: u8 = 2;
+
+/// Return a future that reads the sensor value of type `sensor_type` in the sensor manager task, waiting up to
+/// `timeout` to lock the sensor. The value is converted like the values for sensor listeners, with the key `sensor_key`.
+/// Returns `SYS_ENOENT` if the sensor didn't return any data.
+/// If the future is dropped while the sensor manager is reading, e.g. by `executor::with_timeout()`, the drop blocks
+/// the async task's executor until the read completes, which may take up to `timeout` plus the read time.
+pub fn read_async(sensor: sensor_ptr, sensor_type: sensor_type_t, sensor_key: &'static Strn<'static>, timeout: Duration) -> SensorRead {
+    SensorRead {
+        event:  UnsafeCell::new(zeroed()),
+        sensor,
+        sensor_type,
+        sensor_key,
+        timeout,
+        state:  AtomicU8::new(IDLE),
+        result: UnsafeCell::new(Err(MynewtError::SYS_ENOENT)),
+        wakers: WakerList::new(),
+        _pinned: PhantomPinned,
+    }
+}
+
+/// Future returned by `read_async()`. If dropped while the sensor manager is reading, the drop waits for the read to complete.
+pub struct SensorRead {
+    /// Mynewt event posted to the sensor manager queue. Points to this future, so the future must not move after it's polled.
+    event:       UnsafeCell<os::os_event>,
+    /// Sensor to read
+    sensor:      sensor_ptr,
+    /// Type of sensor value to read
+    sensor_type: sensor_type_t,
+    /// Key of the sensor value
+    sensor_key:  &'static Strn<'static>,
+    /// Max time to wait for the sensor lock
+    timeout:     Duration,
+    /// `IDLE`, `QUEUED` or `DONE`
+    state:       AtomicU8,
+    /// Sensor value, updated by the sensor manager task
+    result:      UnsafeCell<MynewtResult<SensorValue<'static>>>,
+    /// Wakers to be woken when the read completes
+    wakers:      WakerList,
+    /// Event points to the future
+    _pinned:     PhantomPinned,
+}
+
+/// Event is not posted until the future is pinned and polled, so the future may be sent before that
+unsafe impl Send for SensorRead {}
+
+impl Future for SensorRead {
+    type Output = MynewtResult<SensorValue<'static>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };  //  Event will not be moved
+        if this.state.load(Ordering::Acquire) == IDLE {
+            let event = this.event.get();
+            unsafe {
+                (*event).ev_cb  = Some(read_callback);
+                (*event).ev_arg = this as *const Self as Ptr;
+            }
+            this.wakers.replace(cx.waker());
+            this.state.store(QUEUED, Ordering::Release);
+            unsafe { os::os_eventq_put(manager_queue().as_ptr(), event) };
+            return Poll::Pending;
+        }
+        //  Sensor manager updates the state and wakes us in a critical section, so we won't miss the wakeup
+        critical_section(|_cs| {
+            if this.state.load(Ordering::Acquire) == DONE {
+                Poll::Ready(unsafe { *this.result.get() })
+            } else {
+                this.wakers.replace(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl Drop for SensorRead {
+    /// Cancel the read if it's queued. If the sensor manager is reading, wait for the read to complete.
+    fn drop(&mut self) {
+        if self.state.load(Ordering::Acquire) != QUEUED { return; }
+        let cancelled = critical_section(|_cs| {
+            if unsafe { (*self.event.get()).ev_queued } == 0 { return false; }
+            unsafe { os::os_eventq_remove(manager_queue().as_ptr(), self.event.get()) };
+            true
+        });
+        if cancelled { return; }
+        while self.state.load(Ordering::Acquire) != DONE {
+            time::delay(Duration::from_ticks(1));  //  Let the sensor manager finish the read
+        }
+    }
+}
+
+/// Mynewt callback for `SensorRead`, called by the sensor manager task. Reads the sensor and wakes the async task.
+extern "C" fn read_callback(event: *mut os::os_event) {
+    let read = unsafe { &*((*event).ev_arg as *const SensorRead) };
+    let res = super::read(  //  Safe wrapper for `sensor_read()`
+        read.sensor,
+        read.sensor_type,
+        Some(read_data_callback),
+        read as *const SensorRead as Ptr,
+        read.timeout.to_ticks_ceil()
+    );
+    if let Err(err) = res {
+        unsafe { *read.result.get() = Err(err) };
+    }
+    //  Future may be dropped as soon as the state is `DONE`, so we wake it in the same critical section
+    critical_section(|_cs| {
+        read.state.store(DONE, Ordering::Release);
+        read.wakers.wake();
+    });
+}
+
+/// Sensor data function for `SensorRead`. Converts the sensor data to the sensor value.
+extern "C" fn read_data_callback(sensor: sensor_ptr, arg: sensor_arg, sensor_data: sensor_data_ptr, sensor_type: sensor_type_t) -> i32 {
+    let read = unsafe { &*(arg as *const SensorRead) };
+    if sensor_data.is_null() || sensor.is_null() { return MynewtError::SYS_EINVAL.code(); }
+    let value = convert_sensor_data(sensor_data, read.sensor_key, sensor_type);
+    unsafe { *read.result.get() = Ok(value) };
+    0
+}
@@ -7,3 +7,61 @@ use crate::{
 
 /// Contains Rust bindings for Mynewt OS API `kernel/os`
 pub mod os;  // Export `kernel/os.rs` as Rust module `mynewt::kernel::os`
+
+/// Safe Task API for running Rust functions as Mynewt tasks
+pub mod task;  // Export `kernel/task.rs` as Rust module `mynewt::kernel::task`
+
+/// Bounded channels for sending Rust values between tasks and interrupt handlers
+pub mod channel;  // Export `kernel/channel.rs` as Rust module `mynewt::kernel::channel`
+
+/// Wall-clock date and time with civil calendar conversion
+pub mod datetime;  // Export `kernel/datetime.rs` as Rust module `mynewt::kernel::datetime`
+
+/// CPU time and hardware timers with microsecond precision
+pub mod cputime;  // Export `kernel/cputime.rs` as Rust module `mynewt::kernel::cputime`
+
+/// Interrupt-safe critical sections and data shared with interrupt handlers
+pub mod critical;  // Export `kernel/critical.rs` as Rust module `mynewt::kernel::critical`
+
+/// Device handles and Rust-implemented devices
+pub mod device;  // Export `kernel/device.rs` as Rust module `mynewt::kernel::device`
+
+/// Event Queues and Events with Rust handlers
+pub mod event;  // Export `kernel/event.rs` as Rust module `mynewt::kernel::event`
+
+/// Async/await executor driven by event queues, with async sleep and timeouts
+#[cfg(feature = "async")]
+pub mod executor;  // Export `kernel/executor.rs` as Rust module `mynewt::kernel::executor`
+
+/// Rust global allocator on the Mynewt heap, with allocation counters
+#[cfg(feature = "alloc")]
+pub mod heap;  // Export `kernel/heap.rs` as Rust module `mynewt::kernel::heap`
+
+/// Owned Mbuf chains that are freed when dropped
+pub mod mbuf;  // Export `kernel/mbuf.rs` as Rust module `mynewt::kernel::mbuf`
+
+/// Memory pool and MSYS statistics with low-water alarms
+pub mod memstats;  // Export `kernel/memstats.rs` as Rust module `mynewt::kernel::memstats`
+
+/// Mbuf Queues paired with Event Queues, with Rust handlers for each packet
+pub mod mqueue;  // Export `kernel/mqueue.rs` as Rust module `mynewt::kernel::mqueue`
+
+/// Fixed-size typed memory pools with owned blocks
+pub mod pool;  // Export `kernel/pool.rs` as Rust module `mynewt::kernel::pool`
+
+/// Sanity checks for hung tasks and subsystems
+pub mod sanity;  // Export `kernel/sanity.rs` as Rust module `mynewt::kernel::sanity`
+
+/// Semaphore and Mutex built on Mynewt semaphores and mutexes
+pub mod sync;  // Export `kernel/sync.rs` as Rust module `mynewt::kernel::sync`
+
+/// Time API for Mynewt, including durations for timeouts
+pub mod time;  // Export `kernel/time.rs` as Rust module `mynewt::kernel::time`
+
+/// Time-change listeners with Rust callbacks, called when the clock is set
+pub mod time_change;  // Export `kernel/time_change.rs` as Rust module `mynewt::kernel::time_change`
+
+/// Software timers with Rust handlers
+pub mod timer;  // Export `kernel/timer.rs` as Rust module `mynewt::kernel::timer`
+
+pub use task::tasks;  // Export `task::tasks()` as `mynewt::kernel::tasks()`
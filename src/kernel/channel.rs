@@ -0,0 +1,225 @@
+//! Bounded channels for sending Rust values between tasks and interrupt handlers, built on `os_sem` and a
+//! fixed-size ring buffer. Any task or interrupt handler may send, usually one task receives.
+//! A channel may be bound to an event queue, so that the receiving task waits for channel items and OS events together:
+//! ```
+//! /// Channel that sends heart rate readings from the sensor listener to the UI task, processed by the UI event queue
+//! static READINGS: Channel<u32, 8> = Channel::with_queue(&UI_QUEUE, on_readings);
+//!
+//! fn on_readings(readings: &'static Channel<u32, 8>) {
+//!     while let Some(bpm) = readings.try_recv() { show_heart_rate(bpm); }
+//! }
+//!
+//! READINGS.try_send(72).ok();  //  In the sensor listener or an interrupt handler: Drop the reading if the UI is behind
+//! loop { UI_QUEUE.run(); }     //  In the UI task: Handle channel items and other events
+//!
+//! let bpm = READINGS.recv(Duration::from_secs(1)) ? ;  //  Or wait for the next item without an event queue
+//! ```
+
+use core::{
+    cell::{ RefCell, UnsafeCell },
+    mem::MaybeUninit,
+};
+use crate::{
+    kernel::{
+        os,
+        critical::IrqMutex,
+        event::EventQueue,
+        sync::Semaphore,
+        time::{ Duration, Instant },
+    },
+    result::*,
+    util::zeroable::zeroed,
+};
+
+/// Bounded channel that holds up to `N` values of type `T`. May be used in `static` declarations.
+#[repr(C)]
+pub struct Channel<T: Send + 'static, const N: usize> {
+    /// Mynewt event posted to the bound queue when a value is sent. Must be the first field,
+    /// because the event callback converts the `os_event` pointer to `Channel<T, N>`.
+    event:   UnsafeCell<os::os_event>,
+    /// Event queue that is notified when a value is sent, or `None` if not bound
+    queue:   Option<&'static EventQueue>,
+    /// Rust handler called by the task that processes the bound queue
+    handler: Option<fn(&'static Channel<T, N>)>,
+    /// Values in the channel. May be updated by interrupt handlers.
+    ring:    IrqMutex<RefCell<Ring<T, N>>>,
+    /// One token for each value in the channel
+    items:   Semaphore,
+    /// Released when a value is received, to wake up a blocked sender
+    space:   Semaphore,
+}
+
+/// Channels may be used by multiple tasks and interrupt handlers. Values are moved between them.
+unsafe impl<T: Send + 'static, const N: usize> Sync for Channel<T, N> {}
+
+impl<T: Send + 'static, const N: usize> Channel<T, N> {
+    /// Return an empty channel that is not bound to an event queue
+    pub const fn new() -> Self {
+        Channel::create(None, None)
+    }
+
+    /// Return an empty channel that is bound to `queue`. When values are sent, `handler` is called by the task that
+    /// processes `queue`. The handler is called once for multiple values sent together, so it should receive
+    /// with `try_recv()` until the channel is empty.
+    pub const fn with_queue(queue: &'static EventQueue, handler: fn(&'static Channel<T, N>)) -> Self {
+        Channel::create(Some(queue), Some(handler))
+    }
+
+    /// Return an empty channel
+    const fn create(queue: Option<&'static EventQueue>, handler: Option<fn(&'static Channel<T, N>)>) -> Self {
+        assert!(N > 0 && N <= u16::MAX as usize, "bad channel size");
+        Channel {
+            event: UnsafeCell::new(os::os_event {
+                ev_queued: 0,
+                ev_cb:     Some(channel_callback::<T, N>),
+                ev_arg:    core::ptr::null_mut(),
+                ev_next:   zeroed(),
+            }),
+            queue,
+            handler,
+            ring:  IrqMutex::new(RefCell::new(Ring::new())),
+            items: Semaphore::new(0),
+            space: Semaphore::new(0),
+        }
+    }
+
+    /// Send the value, waiting up to `timeout` for space in the channel. Returns the value if the channel
+    /// is still full. `Duration::FOREVER` waits forever. Must not be called by interrupt handlers, use `try_send()` instead.
+    pub fn send(&'static self, mut value: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            value = match self.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(value) => value,
+            };
+            //  Wait for a receiver to free a slot, then try again
+            let remaining = match deadline {
+                Some(deadline) => deadline.duration_since(Instant::now()),
+                None => Duration::FOREVER,
+            };
+            if remaining == Duration::ZERO || self.space.pend(remaining).is_err() {
+                return self.try_send(value);  //  Last try before giving up
+            }
+        }
+    }
+
+    /// Send the value without waiting. Returns the value if the channel is full. May be called by interrupt handlers.
+    pub fn try_send(&'static self, value: T) -> Result<(), T> {
+        self.ring.lock(|ring| ring.push(value)) ? ;
+        self.items.release().expect("chan fail");  //  Wake up the receiver
+        if let Some(queue) = self.queue {
+            unsafe { os::os_eventq_put(queue.as_ptr(), self.event.get()) };  //  Does nothing if already queued
+        }
+        Ok(())
+    }
+
+    /// Receive a value, waiting up to `timeout` for a value to be sent. Returns `OS_TIMEOUT` if the channel is still empty.
+    /// `Duration::ZERO` returns immediately, `Duration::FOREVER` waits forever.
+    pub fn recv(&'static self, timeout: Duration) -> MynewtResult<T> {
+        self.items.pend(timeout) ? ;
+        Ok(self.take())
+    }
+
+    /// Receive a value without waiting. Returns `None` if the channel is empty.
+    pub fn try_recv(&'static self) -> Option<T> {
+        self.items.try_pend().ok() ? ;
+        Some(self.take())
+    }
+
+    /// Receive a value, waiting without blocking the async task's executor.
+    /// Returns `SYS_ENOMEM` if `executor::MAX_WAKERS` other async tasks are waiting to receive.
+    #[cfg(feature = "async")]
+    pub async fn recv_async(&'static self) -> MynewtResult<T> {
+        self.items.pend_async().await ? ;
+        Ok(self.take())
+    }
+
+    /// Return the number of values in the channel
+    pub fn len(&self) -> usize {
+        self.ring.lock(|ring| ring.len)
+    }
+
+    /// Return true if the channel has no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the max number of values in the channel
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Return the event queue that the channel is bound to
+    pub fn event_queue(&self) -> Option<&'static EventQueue> {
+        self.queue
+    }
+
+    /// Take the value for the item token that we have taken. Wake up a blocked sender.
+    fn take(&'static self) -> T {
+        let value = self.ring.lock(|ring| ring.pop())
+            .expect("chan empty");
+        if self.space.tokens() == 0 {
+            self.space.release().expect("chan fail");
+        }
+        value
+    }
+}
+
+/// Mynewt callback for `Channel<T, N>`. Calls the Rust handler.
+extern "C" fn channel_callback<T: Send + 'static, const N: usize>(event: *mut os::os_event) {
+    let channel: &'static Channel<T, N> = unsafe { &*(event as *const Channel<T, N>) };
+    if let Some(handler) = channel.handler {
+        handler(channel);
+    }
+}
+
+/// Ring buffer of up to `N` values
+struct Ring<T, const N: usize> {
+    /// Values, starting at `head`. Only the `len` values from `head` are initialised.
+    buf:  MaybeUninit<[T; N]>,
+    /// Index of the oldest value
+    head: usize,
+    /// Number of values
+    len:  usize,
+}
+
+impl<T, const N: usize> Ring<T, N> {
+    /// Return an empty ring buffer
+    const fn new() -> Self {
+        Ring {
+            buf:  MaybeUninit::uninit(),
+            head: 0,
+            len:  0,
+        }
+    }
+
+    /// Append the value. Returns the value if full.
+    fn push(&mut self, value: T) -> Result<(), T> {
+        if self.len == N { return Err(value); }
+        unsafe { self.slot((self.head + self.len) % N).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Remove the oldest value. Returns `None` if empty.
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 { return None; }
+        let value = unsafe { self.slot(self.head).read() };
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Return a pointer to the value at `index`, which must be less than `N`
+    fn slot(&mut self, index: usize) -> *mut T {
+        debug_assert!(index < N);
+        unsafe { (self.buf.as_mut_ptr() as *mut T).add(index) }
+    }
+}
+
+impl<T, const N: usize> Drop for Ring<T, N> {
+    /// Drop the values remaining in the ring buffer
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
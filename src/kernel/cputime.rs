@@ -0,0 +1,263 @@
+//! CPU time and hardware timers for Mynewt, built on `os_cputime` and `hal_timer`. CPU time is measured by a
+//! hardware timer (`OS_CPUTIME_FREQ`, 1 MHz by default, 32768 Hz with the `cputime_32768` feature), so it's much more precise than the OS ticks used by `Instant`.
+//! `HwTimer` handlers are called in interrupt context, so they must not block:
+//! ```
+//! /// Timer that samples the heart rate sensor
+//! static SAMPLE_TIMER: HwTimer = HwTimer::new(sample_heart_rate);
+//!
+//! fn sample_heart_rate(timer: &'static HwTimer) {
+//!     //  Schedule the next sample relative to this expiry, so the sampling doesn't drift
+//!     let next = timer.expiry().checked_add_micros(40_000).expect("cputime overflow");
+//!     timer.start_at(next).ok();
+//!     SAMPLE_EVENT.post(&HRS_QUEUE);  //  Read the sensor in a task
+//! }
+//!
+//! SAMPLE_TIMER.start_after_micros(40_000) ? ;
+//! let start = CpuTime::now();
+//! cputime::delay_micros(10);
+//! let elapsed = start.elapsed_micros();
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    cmp::Ordering as CmpOrdering,
+    sync::atomic::{ AtomicBool, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr,
+};
+
+/// Frequency of CPU time in ticks per second. Must match the `OS_CPUTIME_FREQ` setting for the BSP:
+/// enable the `cputime_32768` feature if CPU time runs on the 32768 Hz clock.
+#[cfg(not(feature = "cputime_32768"))]
+pub const OS_CPUTIME_FREQ: u32 = 1_000_000;
+
+/// Frequency of CPU time in ticks per second, selected by the `cputime_32768` feature.
+/// Must match the `OS_CPUTIME_FREQ` setting for the BSP.
+#[cfg(feature = "cputime_32768")]
+pub const OS_CPUTIME_FREQ: u32 = 32_768;
+
+/// Return the frequency of CPU time in ticks per second
+pub const fn frequency() -> u32 {
+    OS_CPUTIME_FREQ
+}
+
+/// Return the number of CPU time ticks in `micros` microseconds, rounded down. Returns `None` if too large for `u32`.
+pub fn micros_to_ticks(micros: u32) -> Option<u32> {
+    let ticks = micros as u64 * frequency() as u64 / 1_000_000;
+    if ticks > u32::MAX as u64 { None } else { Some(ticks as u32) }
+}
+
+/// Return the number of microseconds in `ticks` CPU time ticks, rounded down. Returns `None` if too large for `u32`.
+pub fn ticks_to_micros(ticks: u32) -> Option<u32> {
+    let micros = ticks as u64 * 1_000_000 / frequency() as u64;
+    if micros > u32::MAX as u64 { None } else { Some(micros as u32) }
+}
+
+/// Return the number of CPU time ticks in `nanos` nanoseconds, rounded down
+pub fn nanos_to_ticks(nanos: u32) -> u32 {
+    (nanos as u64 * frequency() as u64 / 1_000_000_000) as u32
+}
+
+/// Return the number of nanoseconds in `ticks` CPU time ticks, rounded down. Returns `None` if too large for `u32`.
+pub fn ticks_to_nanos(ticks: u32) -> Option<u32> {
+    let nanos = ticks as u64 * 1_000_000_000 / frequency() as u64;
+    if nanos > u32::MAX as u64 { None } else { Some(nanos as u32) }
+}
+
+/// Wait for `micros` microseconds without sleeping. Other tasks of the same or lower priority won't run.
+pub fn delay_micros(micros: u32) {
+    unsafe { os::os_cputime_delay_usecs(micros) };
+}
+
+/// Wait for at least `nanos` nanoseconds without sleeping. Other tasks of the same or lower priority won't run.
+pub fn delay_nanos(nanos: u32) {
+    //  Round up, like `os_cputime_delay_nsecs()`, which is not defined for power-of-2 frequencies.
+    let scaled = nanos as u64 * frequency() as u64;
+    let ticks = match scaled % 1_000_000_000 {
+        0 => scaled / 1_000_000_000,
+        _ => scaled / 1_000_000_000 + 1,
+    };
+    unsafe { os::os_cputime_delay_ticks(ticks as u32) };
+}
+
+/// Point in CPU time, measured in CPU time ticks. The tick counter wraps around, so CPU times may only be
+/// compared and subtracted if they are less than half the tick range apart (about 35 minutes at 1 MHz).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CpuTime {
+    /// CPU time tick count
+    ticks: u32,
+}
+
+impl CpuTime {
+    /// Return the current CPU time. May be called by interrupt handlers.
+    pub fn now() -> CpuTime {
+        CpuTime { ticks: unsafe { os::os_cputime_get32() } }
+    }
+
+    /// Return the CPU time for the tick count
+    pub const fn from_ticks(ticks: u32) -> CpuTime {
+        CpuTime { ticks }
+    }
+
+    /// Return the tick count
+    pub const fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Return the number of ticks from `earlier` to this CPU time, or `None` if `earlier` is later. Handles wraparound.
+    pub fn ticks_since(&self, earlier: CpuTime) -> Option<u32> {
+        let diff = self.ticks.wrapping_sub(earlier.ticks);
+        if (diff as i32) < 0 { None }  //  Same as `CPUTIME_LT()`
+        else { Some(diff) }
+    }
+
+    /// Return the microseconds from `earlier` to this CPU time, or `None` if `earlier` is later
+    pub fn micros_since(&self, earlier: CpuTime) -> Option<u32> {
+        self.ticks_since(earlier).and_then(ticks_to_micros)
+    }
+
+    /// Return the nanoseconds from `earlier` to this CPU time, or `None` if `earlier` is later or too long ago for `u32`
+    pub fn nanos_since(&self, earlier: CpuTime) -> Option<u32> {
+        self.ticks_since(earlier).and_then(ticks_to_nanos)
+    }
+
+    /// Return the microseconds elapsed since this CPU time
+    pub fn elapsed_micros(&self) -> Option<u32> {
+        CpuTime::now().micros_since(*self)
+    }
+
+    /// Return the CPU time `ticks` after this CPU time, or `None` if too far to be compared with this CPU time
+    pub fn checked_add_ticks(&self, ticks: u32) -> Option<CpuTime> {
+        if ticks > i32::MAX as u32 { return None; }
+        Some(CpuTime { ticks: self.ticks.wrapping_add(ticks) })
+    }
+
+    /// Return the CPU time `ticks` before this CPU time, or `None` if too far to be compared with this CPU time
+    pub fn checked_sub_ticks(&self, ticks: u32) -> Option<CpuTime> {
+        if ticks > i32::MAX as u32 { return None; }
+        Some(CpuTime { ticks: self.ticks.wrapping_sub(ticks) })
+    }
+
+    /// Return the CPU time `micros` microseconds after this CPU time, or `None` if too far
+    pub fn checked_add_micros(&self, micros: u32) -> Option<CpuTime> {
+        micros_to_ticks(micros).and_then(|ticks| self.checked_add_ticks(ticks))
+    }
+
+    /// Return the CPU time `micros` microseconds before this CPU time, or `None` if too far
+    pub fn checked_sub_micros(&self, micros: u32) -> Option<CpuTime> {
+        micros_to_ticks(micros).and_then(|ticks| self.checked_sub_ticks(ticks))
+    }
+
+    /// Return the CPU time `nanos` nanoseconds after this CPU time, or `None` if too far
+    pub fn checked_add_nanos(&self, nanos: u32) -> Option<CpuTime> {
+        self.checked_add_ticks(nanos_to_ticks(nanos))
+    }
+}
+
+/// Compare two CPU times, handling wraparound
+impl PartialOrd for CpuTime {
+    fn partial_cmp(&self, other: &CpuTime) -> Option<CmpOrdering> {
+        Some((self.ticks.wrapping_sub(other.ticks) as i32).cmp(&0))
+    }
+}
+
+/// Hardware timer that calls a Rust handler in interrupt context when it expires. The handler may restart the timer.
+/// Declare as `static`, the timer is initialised when first started.
+pub struct HwTimer {
+    /// Mynewt HAL timer. Its callback argument points to this `HwTimer` when initialised.
+    timer:   UnsafeCell<os::hal_timer>,
+    /// Rust handler for the timer, called in interrupt context
+    handler: fn(&'static HwTimer),
+    /// True if the HAL timer has been initialised
+    inited:  AtomicBool,
+}
+
+/// HAL timers may be started and stopped by multiple tasks and interrupt handlers
+unsafe impl Sync for HwTimer {}
+
+impl HwTimer {
+    /// Return a stopped timer that calls `handler` in interrupt context when it expires.
+    /// May be used in `static` declarations.
+    pub const fn new(handler: fn(&'static HwTimer)) -> Self {
+        HwTimer {
+            timer:   UnsafeCell::new(zeroed()),
+            handler,
+            inited:  AtomicBool::new(false),
+        }
+    }
+
+    /// Start the timer to expire `micros` microseconds from now. Restarts the timer if it's running.
+    /// May be called by interrupt handlers, including the timer handler.
+    pub fn start_after_micros(&'static self, micros: u32) -> MynewtResult<()> {
+        let timer = self.as_ptr();
+        let rc = critical_section(|_cs| {
+            unsafe { os::os_cputime_timer_stop(timer) };
+            unsafe { os::os_cputime_timer_relative(timer, micros) }
+        });
+        if rc != 0 { return Err(MynewtError::from(rc)); }
+        Ok(())
+    }
+
+    /// Start the timer to expire at the CPU time `at`. If `at` has passed, the handler is called right away.
+    /// Restarts the timer if it's running. May be called by interrupt handlers, including the timer handler.
+    pub fn start_at(&'static self, at: CpuTime) -> MynewtResult<()> {
+        let timer = self.as_ptr();
+        let rc = critical_section(|_cs| {
+            unsafe { os::os_cputime_timer_stop(timer) };
+            unsafe { os::os_cputime_timer_start(timer, at.ticks()) }
+        });
+        if rc != 0 { return Err(MynewtError::from(rc)); }
+        Ok(())
+    }
+
+    /// Stop the timer, cancelling the pending expiry. Returns true if the timer was running.
+    /// May be called by interrupt handlers, including the timer handler.
+    pub fn stop(&'static self) -> bool {
+        if !self.inited.load(Ordering::Acquire) { return false; }
+        let timer = self.timer.get();
+        critical_section(|_cs| {
+            let active = self.is_active();
+            unsafe { os::os_cputime_timer_stop(timer) };
+            active
+        })
+    }
+
+    /// Return true if the timer is running
+    pub fn is_active(&self) -> bool {
+        //  HAL timers are linked into the timer queue while running
+        unsafe { !(*self.timer.get()).link.tqe_prev.is_null() }
+    }
+
+    /// Return the CPU time when the timer expires, or expired if it's not running
+    pub fn expiry(&self) -> CpuTime {
+        CpuTime::from_ticks(unsafe { (*self.timer.get()).expiry })
+    }
+
+    /// Return the Mynewt HAL timer. Initialise the timer if it hasn't been initialised.
+    pub fn as_ptr(&'static self) -> *mut os::hal_timer {
+        let timer = self.timer.get();
+        if !self.inited.load(Ordering::Acquire) {
+            //  Timer may be started by multiple tasks, so we lock out interrupts while initialising.
+            critical_section(|_cs| {
+                if !self.inited.load(Ordering::Acquire) {
+                    unsafe { os::os_cputime_timer_init(timer, Some(hwtimer_callback), self as *const Self as Ptr) };
+                    self.inited.store(true, Ordering::Release);
+                }
+            });
+        }
+        timer
+    }
+}
+
+/// Mynewt HAL timer callback for `HwTimer`, called in interrupt context. Calls the Rust handler.
+extern "C" fn hwtimer_callback(arg: Ptr) {
+    let timer: &'static HwTimer = unsafe { &*(arg as *const HwTimer) };
+    (timer.handler)(timer);
+}
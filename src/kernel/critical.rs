@@ -0,0 +1,120 @@
+//! Interrupt-safe critical sections for Mynewt, built on `os_arch_save_sr()` and `os_arch_restore_sr()`.
+//! Data shared with interrupt handlers is declared as `IrqMutex`, which may only be accessed with the
+//! `CriticalSection` token passed to `critical_section()`:
+//! ```
+//! /// Number of SPI transfers completed, updated by the SPI interrupt handler
+//! static SPI_DONE: IrqMutex<Cell<u32>> = IrqMutex::new(Cell::new(0));
+//! /// Last sensor reading, shared between tasks and interrupt handlers
+//! static READING: IrqMutex<RefCell<[u16; 3]>> = IrqMutex::new(RefCell::new([0; 3]));
+//!
+//! critical_section(|cs| {
+//!     let done = SPI_DONE.borrow(cs);
+//!     done.set(done.get() + 1);
+//!     READING.borrow(cs).borrow_mut()[0] = 42;
+//! });
+//! ```
+//! With the `critical_section_impl` feature, this crate is also the provider for the `critical-section` crate,
+//! so that crates like `heapless` and `embedded-hal` drivers may be shared with interrupt handlers on Mynewt.
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+};
+use crate::kernel::os;
+
+/// Token that proves interrupts are locked out. Only `critical_section()` may create it,
+/// and it can't escape the critical section.
+#[derive(Clone, Copy)]
+pub struct CriticalSection<'cs> {
+    /// Token is bound to the lifetime of the critical section
+    _scope: PhantomData<&'cs ()>,
+}
+
+impl<'cs> CriticalSection<'cs> {
+    /// Return a token for a critical section that the caller has entered.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that interrupts are locked out for `'cs`, e.g. with `os_arch_save_sr()`,
+    /// and that the token doesn't outlive the critical section.
+    pub unsafe fn new() -> Self {
+        CriticalSection { _scope: PhantomData }
+    }
+}
+
+/// Lock out interrupts, call `f` with the critical section token and restore interrupts.
+/// Critical sections may be nested, interrupts are restored when the outermost section ends.
+/// Keep the section short, and don't call functions that may block, e.g. `Semaphore::pend()`.
+pub fn critical_section<F, R>(f: F) -> R
+where
+    F: FnOnce(CriticalSection) -> R,
+{
+    //  If `f` panics, interrupts stay locked out. That's OK because Mynewt resets the system on panic.
+    let sr = unsafe { os::os_arch_save_sr() };
+    let result = f(unsafe { CriticalSection::new() });
+    unsafe { os::os_arch_restore_sr(sr) };
+    result
+}
+
+/// Data shared between tasks and interrupt handlers, accessed only in a critical section.
+/// Wrap the data in `Cell` or `RefCell` to update it. May be used in `static` declarations.
+pub struct IrqMutex<T> {
+    /// Shared data
+    data: UnsafeCell<T>,
+}
+
+/// Data may only be accessed with interrupts locked out, so only one task or interrupt handler may access it at a time
+unsafe impl<T: Send> Sync for IrqMutex<T> {}
+
+impl<T> IrqMutex<T> {
+    /// Return a mutex containing `data`
+    pub const fn new(data: T) -> Self {
+        IrqMutex { data: UnsafeCell::new(data) }
+    }
+
+    /// Return a reference to the data, valid until the critical section ends
+    pub fn borrow<'cs>(&'cs self, _cs: CriticalSection<'cs>) -> &'cs T {
+        unsafe { &*self.data.get() }
+    }
+
+    /// Return a mutable reference to the data. No critical section is needed because the mutex is not shared.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Return the data
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T> IrqMutex<core::cell::RefCell<T>> {
+    /// Enter a critical section and call `f` with a mutable reference to the data.
+    /// Panics if the data is already borrowed, e.g. by a nested call.
+    pub fn lock<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        critical_section(|cs| f(&mut self.borrow(cs).borrow_mut()))
+    }
+}
+
+/// Provider for the `critical-section` crate, built on `os_arch_save_sr()` and `os_arch_restore_sr()`
+#[cfg(feature = "critical_section_impl")]
+mod provider {
+    use crate::kernel::os;
+
+    /// Mynewt implementation of `critical_section::Impl`
+    struct MynewtCriticalSection;
+    critical_section::set_impl!(MynewtCriticalSection);
+
+    unsafe impl critical_section::Impl for MynewtCriticalSection {
+        unsafe fn acquire() -> critical_section::RawRestoreState {
+            os::os_arch_save_sr()
+        }
+
+        unsafe fn release(sr: critical_section::RawRestoreState) {
+            os::os_arch_restore_sr(sr)
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Wall-clock date and time for Mynewt, built on `os_gettimeofday()` and `os_settimeofday()`.
+//! Converts between UNIX timestamps and the civil calendar (proleptic Gregorian) with integer arithmetic only.
+//! ```
+//! //  Set the clock when the phone syncs the time
+//! datetime::set_clock_iso8601("2020-05-14T10:30:00+08:00") ? ;
+//!
+//! //  Display the time on the watch face
+//! let now = DateTime::now() ? ;
+//! let mut buf: StrnBuf<16> = StrnBuf::new();
+//! write!(buf, "{}", now.format(Format::Time24)) ? ;  //  "10:30"
+//! write!(buf, "{}", now.format(Format::DateShort)) ? ;  //  "Thu 14 May"
+//! ```
+
+use core::fmt;
+use crate::{
+    kernel::os,
+    result::*,
+};
+
+/// Number of seconds in a day
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Days from 0000-03-01 to 1970-01-01 in the proleptic Gregorian calendar
+const DAYS_TO_UNIX_EPOCH: i64 = 719_468;
+
+/// Earliest supported year
+pub const MIN_YEAR: u16 = 1970;
+
+/// Latest supported year, the last year that fits in 4 digits
+pub const MAX_YEAR: u16 = 9999;
+
+/// Full names of the months, starting from January
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Time zone: offset from UTC and daylight saving time. Same as `os_timezone`, but with the offset east of UTC.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TimeZone {
+    /// Standard time offset in minutes east of UTC, e.g. 480 for UTC+08:00
+    offset_minutes: i16,
+    /// True if daylight saving time is in effect, which adds 60 minutes to the offset
+    dst: bool,
+}
+
+impl TimeZone {
+    /// UTC time zone
+    pub const UTC: TimeZone = TimeZone { offset_minutes: 0, dst: false };
+
+    /// Return a time zone `offset_minutes` east of UTC. `dst` is true if daylight saving time is in effect,
+    /// which adds 60 minutes. Returns `SYS_EINVAL` if the total offset is more than 24 hours.
+    pub fn new(offset_minutes: i16, dst: bool) -> MynewtResult<TimeZone> {
+        let tz = TimeZone { offset_minutes, dst };
+        if tz.total_offset_minutes().abs() >= 24 * 60 { return Err(MynewtError::SYS_EINVAL); }
+        Ok(tz)
+    }
+
+    /// Return the standard time offset in minutes east of UTC, without daylight saving time
+    pub fn offset_minutes(&self) -> i16 {
+        self.offset_minutes
+    }
+
+    /// Return true if daylight saving time is in effect
+    pub fn is_dst(&self) -> bool {
+        self.dst
+    }
+
+    /// Return the offset from UTC in minutes, including daylight saving time.
+    /// Same as Mynewt's `datetime_format()`: DST adds 60 minutes.
+    pub fn total_offset_minutes(&self) -> i32 {
+        self.offset_minutes as i32 + if self.dst { 60 } else { 0 }
+    }
+
+    /// Return the time zone with daylight saving time switched on or off
+    pub fn with_dst(&self, dst: bool) -> TimeZone {
+        TimeZone { offset_minutes: self.offset_minutes, dst }
+    }
+
+    /// Convert from Mynewt time zone, which is in minutes west of UTC
+    fn from_os(tz: &os::os_timezone) -> TimeZone {
+        TimeZone { offset_minutes: -tz.tz_minuteswest, dst: tz.tz_dsttime != 0 }
+    }
+
+    /// Convert to Mynewt time zone, which is in minutes west of UTC
+    fn to_os(&self) -> os::os_timezone {
+        os::os_timezone { tz_minuteswest: -self.offset_minutes, tz_dsttime: self.dst as i16 }
+    }
+}
+
+/// Day of the week
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Return the weekday for the number of days since 1970-01-01, which was a Thursday
+    fn from_days(days: i64) -> Weekday {
+        use Weekday::*;
+        match (days + 3).rem_euclid(7) {
+            0 => Monday,
+            1 => Tuesday,
+            2 => Wednesday,
+            3 => Thursday,
+            4 => Friday,
+            5 => Saturday,
+            _ => Sunday,
+        }
+    }
+
+    /// Return the ISO 8601 weekday number: 1 for Monday to 7 for Sunday
+    pub fn number_from_monday(&self) -> u8 {
+        *self as u8 + 1
+    }
+
+    /// Return the full name, e.g. `Thursday`
+    pub fn name(&self) -> &'static str {
+        use Weekday::*;
+        match self {
+            Monday    => "Monday",
+            Tuesday   => "Tuesday",
+            Wednesday => "Wednesday",
+            Thursday  => "Thursday",
+            Friday    => "Friday",
+            Saturday  => "Saturday",
+            Sunday    => "Sunday",
+        }
+    }
+
+    /// Return the 3-letter name, e.g. `Thu`
+    pub fn short_name(&self) -> &'static str {
+        &self.name()[..3]
+    }
+}
+
+/// Return true if `year` is a leap year in the Gregorian calendar
+pub fn is_leap_year(year: u16) -> bool {
+    match (year % 4, year % 100, year % 400) {
+        (_, _, 0) => true,   //  Every 400 years, e.g. 2000
+        (_, 0, _) => false,  //  Except other centuries, e.g. 2100
+        (0, _, _) => true,   //  Every 4 years
+        _         => false,
+    }
+}
+
+/// Return the number of days in the month (1 to 12) of the year
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Return the number of days from 1970-01-01 to the date. Based on `days_from_civil()` from
+/// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: u16, month: u8, day: u8) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };  //  Year starts in March
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;                                  //  Year of era: [0, 399]
+    let mp = (month as i64 + 9) % 12;                         //  Month from March: [0, 11]
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;            //  Day of year: [0, 365]
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;          //  Day of era: [0, 146096]
+    era * 146_097 + doe - DAYS_TO_UNIX_EPOCH
+}
+
+/// Return the (year, month, day) for the number of days since 1970-01-01. Based on `civil_from_days()` from
+/// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + DAYS_TO_UNIX_EPOCH;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;                                          //  Day of era: [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;    //  Year of era: [0, 399]
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);                    //  Day of year from March: [0, 365]
+    let mp = (5 * doy + 2) / 153;                                         //  Month from March: [0, 11]
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;                       //  [1, 31]
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;            //  [1, 12]
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Local date and time in a time zone, with microsecond precision
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    timestamp: i64,
+    /// Microseconds after the second
+    micros:    u32,
+    /// Time zone for the local date and time
+    tz:        TimeZone,
+    /// Local year, 1970 to 9999
+    year:      u16,
+    /// Local month, 1 to 12
+    month:     u8,
+    /// Local day of the month, 1 to 31
+    day:       u8,
+    /// Local hour, 0 to 23
+    hour:      u8,
+    /// Local minute, 0 to 59
+    minute:    u8,
+    /// Local second, 0 to 59
+    second:    u8,
+    /// Local day of the week
+    weekday:   Weekday,
+}
+
+impl DateTime {
+    /// Return the current date and time from the clock, in the clock's time zone.
+    /// If the clock has not been set (see `is_clock_set()`), the time starts from 1970-01-01 00:00:00 UTC at boot.
+    pub fn now() -> MynewtResult<DateTime> {
+        let mut tv = os::os_timeval { tv_sec: 0, tv_usec: 0 };
+        let mut tz = os::os_timezone { tz_minuteswest: 0, tz_dsttime: 0 };
+        let rc = unsafe { os::os_gettimeofday(&mut tv, &mut tz) };
+        if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        DateTime::from_timestamp(tv.tv_sec, tv.tv_usec as u32, TimeZone::from_os(&tz))
+    }
+
+    /// Return the date and time for the UNIX timestamp (seconds since 1970-01-01 00:00:00 UTC) in the time zone.
+    /// Returns `SYS_EINVAL` if the local date is outside `MIN_YEAR` to `MAX_YEAR` or `micros` is 1,000,000 or more.
+    pub fn from_timestamp(timestamp: i64, micros: u32, tz: TimeZone) -> MynewtResult<DateTime> {
+        if micros >= 1_000_000 { return Err(MynewtError::SYS_EINVAL); }
+        let local = timestamp.checked_add(tz.total_offset_minutes() as i64 * 60)
+            .ok_or(MynewtError::SYS_EINVAL) ? ;
+        let days = local.div_euclid(SECS_PER_DAY);
+        let secs = local.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        if year < MIN_YEAR as i64 || year > MAX_YEAR as i64 { return Err(MynewtError::SYS_EINVAL); }
+        Ok(DateTime {
+            timestamp,
+            micros,
+            tz,
+            year:    year as u16,
+            month,
+            day,
+            hour:    (secs / 3600) as u8,
+            minute:  (secs / 60 % 60) as u8,
+            second:  (secs % 60) as u8,
+            weekday: Weekday::from_days(days),
+        })
+    }
+
+    /// Return the date and time for the local date and time in the time zone.
+    /// Returns `SYS_EINVAL` if any field is out of range, e.g. February 29 in a non-leap year.
+    pub fn from_civil(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8, tz: TimeZone) -> MynewtResult<DateTime> {
+        if !(MIN_YEAR..=MAX_YEAR).contains(&year) ||
+            !(1..=12).contains(&month) ||
+            day < 1 || day > days_in_month(year, month) ||
+            hour > 23 || minute > 59 || second > 59 {
+            return Err(MynewtError::SYS_EINVAL);
+        }
+        let local = days_from_civil(year, month, day) * SECS_PER_DAY
+            + hour as i64 * 3600 + minute as i64 * 60 + second as i64;
+        let timestamp = local - tz.total_offset_minutes() as i64 * 60;
+        DateTime::from_timestamp(timestamp, 0, tz)
+    }
+
+    /// Parse an ISO 8601 date and time like `2020-05-14T10:30:00+08:00`, `2020-05-14 10:30:00.250Z`
+    /// or `2020-05-14T02:30:00`. Fractional seconds are optional, up to 6 digits are kept.
+    /// Time zone is `Z`, `+HH:MM`, `-HH:MM`, `+HHMM` or `-HHMM`. If the time zone is missing, UTC is assumed.
+    /// Returns `SYS_EINVAL` if the string is not in this format.
+    pub fn parse_iso8601(s: &str) -> MynewtResult<DateTime> {
+        let mut p = Parser { bytes: s.as_bytes(), pos: 0 };
+        let year   = p.digits(4) ? as u16;   p.expect(b'-') ? ;
+        let month  = p.digits(2) ? as u8;    p.expect(b'-') ? ;
+        let day    = p.digits(2) ? as u8;
+        if !p.accept(b'T') && !p.accept(b't') && !p.accept(b' ') { return Err(MynewtError::SYS_EINVAL); }
+        let hour   = p.digits(2) ? as u8;    p.expect(b':') ? ;
+        let minute = p.digits(2) ? as u8;    p.expect(b':') ? ;
+        let second = p.digits(2) ? as u8;
+        //  Parse the optional fractional seconds, keeping up to 6 digits.
+        let mut micros = 0;
+        if p.accept(b'.') {
+            let mut scale = 100_000;
+            let start = p.pos;
+            while let Some(digit) = p.digit() {
+                micros += digit * scale;
+                scale /= 10;
+            }
+            if p.pos == start { return Err(MynewtError::SYS_EINVAL); }
+        }
+        //  Parse the optional time zone.
+        let tz = match p.next() {
+            None => TimeZone::UTC,
+            Some(b'Z') | Some(b'z') => TimeZone::UTC,
+            Some(sign @ b'+') | Some(sign @ b'-') => {
+                let hours = p.digits(2) ? as i16;
+                p.accept(b':');
+                let minutes = p.digits(2) ? as i16;
+                if hours > 23 || minutes > 59 { return Err(MynewtError::SYS_EINVAL); }
+                let offset = hours * 60 + minutes;
+                TimeZone::new(if sign == b'-' { -offset } else { offset }, false) ?
+            }
+            Some(_) => return Err(MynewtError::SYS_EINVAL),
+        };
+        if p.pos != p.bytes.len() { return Err(MynewtError::SYS_EINVAL); }
+        let dt = DateTime::from_civil(year, month, day, hour, minute, second, tz) ? ;
+        Ok(DateTime { micros, ..dt })
+    }
+
+    /// Return the same instant in another time zone
+    pub fn with_timezone(&self, tz: TimeZone) -> MynewtResult<DateTime> {
+        DateTime::from_timestamp(self.timestamp, self.micros, tz)
+    }
+
+    /// Return the UNIX timestamp: seconds since 1970-01-01 00:00:00 UTC
+    pub fn timestamp(&self) -> i64 { self.timestamp }
+
+    /// Return the microseconds after the second
+    pub fn micros(&self) -> u32 { self.micros }
+
+    /// Return the time zone
+    pub fn timezone(&self) -> TimeZone { self.tz }
+
+    /// Return the local year
+    pub fn year(&self) -> u16 { self.year }
+
+    /// Return the local month: 1 for January to 12 for December
+    pub fn month(&self) -> u8 { self.month }
+
+    /// Return the local day of the month: 1 to 31
+    pub fn day(&self) -> u8 { self.day }
+
+    /// Return the local hour: 0 to 23
+    pub fn hour(&self) -> u8 { self.hour }
+
+    /// Return the local hour on a 12-hour clock: 1 to 12
+    pub fn hour12(&self) -> u8 {
+        match self.hour % 12 { 0 => 12, h => h }
+    }
+
+    /// Return true if the local time is after noon
+    pub fn is_pm(&self) -> bool { self.hour >= 12 }
+
+    /// Return the local minute: 0 to 59
+    pub fn minute(&self) -> u8 { self.minute }
+
+    /// Return the local second: 0 to 59
+    pub fn second(&self) -> u8 { self.second }
+
+    /// Return the local day of the week
+    pub fn weekday(&self) -> Weekday { self.weekday }
+
+    /// Return the local day of the year: 1 to 366
+    pub fn day_of_year(&self) -> u16 {
+        (days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1) as u16
+    }
+
+    /// Return the full name of the local month, e.g. `May`
+    pub fn month_name(&self) -> &'static str {
+        MONTH_NAMES[self.month as usize - 1]
+    }
+
+    /// Return the 3-letter name of the local month, e.g. `May`
+    pub fn month_short_name(&self) -> &'static str {
+        &self.month_name()[..3]
+    }
+
+    /// Return a formatter for the date and time that may be passed to `write!()`, e.g. to a `StrnBuf`
+    pub fn format(&self, format: Format) -> Formatted {
+        Formatted { datetime: *self, format }
+    }
+
+    /// Set the clock to this date and time and time zone
+    pub fn set_clock(&self) -> MynewtResult<()> {
+        let mut tv = os::os_timeval { tv_sec: self.timestamp, tv_usec: self.micros as i32 };
+        let mut tz = self.tz.to_os();
+        let rc = unsafe { os::os_settimeofday(&mut tv, &mut tz) };
+        if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        Ok(())
+    }
+}
+
+/// Display as ISO 8601, e.g. `2020-05-14T10:30:00+08:00`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.format(Format::Iso8601), f)
+    }
+}
+
+/// Formats for displaying `DateTime` on watch faces
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// `2020-05-14T10:30:00+08:00`, with `.250000` after the seconds if there are microseconds
+    Iso8601,
+    /// `2020-05-14`
+    Date,
+    /// `Thu 14 May`
+    DateShort,
+    /// `Thursday, 14 May 2020`
+    DateLong,
+    /// `10:30`
+    Time24,
+    /// `10:30:00`
+    Time24Seconds,
+    /// `10:30 AM`
+    Time12,
+}
+
+/// Date and time that will be displayed in a `Format`. Returned by `DateTime::format()`.
+pub struct Formatted {
+    /// Date and time to be displayed
+    datetime: DateTime,
+    /// Format for displaying
+    format:   Format,
+}
+
+impl fmt::Display for Formatted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dt = &self.datetime;
+        match self.format {
+            Format::Iso8601 => {
+                write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second) ? ;
+                if dt.micros != 0 { write!(f, ".{:06}", dt.micros) ? ; }
+                let offset = dt.tz.total_offset_minutes();
+                if offset == 0 { write!(f, "Z") }
+                else {
+                    let sign = if offset < 0 { '-' } else { '+' };
+                    write!(f, "{}{:02}:{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+                }
+            }
+            Format::Date          => write!(f, "{:04}-{:02}-{:02}", dt.year, dt.month, dt.day),
+            Format::DateShort     => write!(f, "{} {} {}", dt.weekday.short_name(), dt.day, dt.month_short_name()),
+            Format::DateLong      => write!(f, "{}, {} {} {}", dt.weekday.name(), dt.day, dt.month_name(), dt.year),
+            Format::Time24        => write!(f, "{:02}:{:02}", dt.hour, dt.minute),
+            Format::Time24Seconds => write!(f, "{:02}:{:02}:{:02}", dt.hour, dt.minute, dt.second),
+            Format::Time12        => write!(f, "{}:{:02} {}", dt.hour12(), dt.minute, if dt.is_pm() { "PM" } else { "AM" }),
+        }
+    }
+}
+
+/// Return true if the clock has been set by `set_clock()` or any other call to `os_settimeofday()`
+pub fn is_clock_set() -> bool {
+    unsafe { os::os_time_is_set() }
+}
+
+/// Set the clock to the UNIX timestamp (seconds since 1970-01-01 00:00:00 UTC) and time zone
+pub fn set_clock(timestamp: i64, tz: TimeZone) -> MynewtResult<()> {
+    DateTime::from_timestamp(timestamp, 0, tz) ?
+        .set_clock()
+}
+
+/// Set the clock to an ISO 8601 date and time like `2020-05-14T10:30:00+08:00`. See `DateTime::parse_iso8601()`.
+pub fn set_clock_iso8601(s: &str) -> MynewtResult<()> {
+    DateTime::parse_iso8601(s) ?
+        .set_clock()
+}
+
+/// Return the time zone of the clock
+pub fn timezone() -> MynewtResult<TimeZone> {
+    Ok(DateTime::now() ?.timezone())
+}
+
+/// Change the time zone of the clock without changing the UTC time, e.g. when daylight saving time starts
+pub fn set_timezone(tz: TimeZone) -> MynewtResult<()> {
+    DateTime::now() ?
+        .with_timezone(tz) ?
+        .set_clock()
+}
+
+/// Byte parser for ISO 8601 strings
+struct Parser<'a> {
+    /// Bytes to be parsed
+    bytes: &'a [u8],
+    /// Position of the next byte
+    pos:   usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Return the next byte and advance, or `None` at the end
+    fn next(&mut self) -> Option<u8> {
+        let b = *self.bytes.get(self.pos) ? ;
+        self.pos += 1;
+        Some(b)
+    }
+
+    /// Advance if the next byte is `b`. Return true if advanced.
+    fn accept(&mut self, b: u8) -> bool {
+        if self.bytes.get(self.pos) == Some(&b) { self.pos += 1; true }
+        else { false }
+    }
+
+    /// Advance over the next byte, which must be `b`
+    fn expect(&mut self, b: u8) -> MynewtResult<()> {
+        if self.accept(b) { Ok(()) }
+        else { Err(MynewtError::SYS_EINVAL) }
+    }
+
+    /// Return the value of the next byte if it's a decimal digit, and advance
+    fn digit(&mut self) -> Option<u32> {
+        match self.bytes.get(self.pos) {
+            Some(b) if b.is_ascii_digit() => { self.pos += 1; Some((b - b'0') as u32) }
+            _ => None,
+        }
+    }
+
+    /// Return the value of the next `n` decimal digits and advance
+    fn digits(&mut self, n: usize) -> MynewtResult<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = value * 10 + self.digit().ok_or(MynewtError::SYS_EINVAL) ? ;
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,390 @@
+//! Device API for Mynewt, built on `os_dev`. `Device` is an open device that is closed when dropped,
+//! `DeviceRef` refers to a registered device without opening it:
+//! ```
+//! let display = Device::open(&init_strn!("spi0"), Duration::from_secs(1)) ? ;  //  Closed when dropped
+//! for dev in device::devices() {
+//!     console::print_strn(&dev.name()); console::print("\n");
+//! }
+//! ```
+//! Devices may also be implemented in Rust with the `DeviceDriver` trait and registered with `DriverDevice`:
+//! ```
+//! struct Backlight;
+//! impl DeviceDriver for Backlight {
+//!     fn open(&'static self, _dev: DeviceRef, _timeout: Duration, _arg: Ptr) -> MynewtResult<()> { ... }
+//! }
+//! static BACKLIGHT: DriverDevice<Backlight> = DriverDevice::new(Backlight);
+//!
+//! BACKLIGHT.create(&init_strn!("backlight"), InitStage::Secondary, os::OS_DEV_INIT_PRIO_DEFAULT as u8) ? ;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    ptr::NonNull,
+    sync::atomic::{ AtomicBool, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        time::{ Duration, Instant },
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr, Strn,
+};
+
+/// State of a Mynewt device, from the device flags
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceState {
+    /// Device flags `OS_DEV_F_*`
+    flags:     u8,
+    /// Number of times the device has been opened and not closed
+    open_refs: u8,
+}
+
+impl DeviceState {
+    /// Return true if the device has been initialised
+    pub fn is_ready(&self) -> bool { self.flags as u32 & os::OS_DEV_F_STATUS_READY != 0 }
+
+    /// Return true if the device is open
+    pub fn is_open(&self) -> bool { self.flags as u32 & os::OS_DEV_F_STATUS_OPEN != 0 }
+
+    /// Return true if the device is suspended
+    pub fn is_suspended(&self) -> bool { self.flags as u32 & os::OS_DEV_F_STATUS_SUSPENDED != 0 }
+
+    /// Return true if the system can't start when the device fails to initialise
+    pub fn is_critical(&self) -> bool { self.flags as u32 & os::OS_DEV_F_INIT_CRITICAL != 0 }
+
+    /// Return the number of times the device has been opened and not closed
+    pub fn open_count(&self) -> u8 { self.open_refs }
+
+    /// Return the device flags `OS_DEV_F_*`
+    pub fn flags(&self) -> u8 { self.flags }
+}
+
+/// Registered Mynewt device, which may or may not be open. Devices are never unregistered.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceRef(NonNull<os::os_dev>);
+
+unsafe impl Send for DeviceRef {}
+unsafe impl Sync for DeviceRef {}
+
+impl DeviceRef {
+    /// Return the registered device named `name`, or `None` if not found or the name is not null-terminated
+    pub fn lookup(name: &Strn) -> Option<DeviceRef> {
+        if !name.is_null_terminated() { return None; }
+        let dev = unsafe { os::os_dev_lookup(name.as_cstr() as *const ::cty::c_char) };
+        unsafe { DeviceRef::from_ptr(dev) }
+    }
+
+    /// Return the device for the Mynewt device, e.g. from `sensor_get_device()`. Returns `None` if null.
+    ///
+    /// # Safety
+    ///
+    /// `dev` must be a registered device.
+    pub unsafe fn from_ptr(dev: *mut os::os_dev) -> Option<DeviceRef> {
+        NonNull::new(dev).map(DeviceRef)
+    }
+
+    /// Open the device, waiting up to `timeout`. The device is closed when the returned `Device` is dropped.
+    pub fn open(&self, timeout: Duration) -> MynewtResult<Device> {
+        Device::open(&self.name(), timeout)
+    }
+
+    /// Return the device name
+    pub fn name(&self) -> Strn<'static> {
+        Strn::from_cstr(unsafe { self.0.as_ref().od_name } as *const u8)
+    }
+
+    /// Return the device state
+    pub fn state(&self) -> DeviceState {
+        let dev = unsafe { self.0.as_ref() };
+        DeviceState { flags: dev.od_flags, open_refs: dev.od_open_ref }
+    }
+
+    /// Suspend the device until `until`. If `force` is true, the device is suspended even if the driver refuses.
+    pub fn suspend(&self, until: Instant, force: bool) -> MynewtResult<()> {
+        let rc = unsafe { os::os_dev_suspend(self.0.as_ptr(), until.ticks(), force as u8) };
+        check(rc)
+    }
+
+    /// Resume the suspended device
+    pub fn resume(&self) -> MynewtResult<()> {
+        let rc = unsafe { os::os_dev_resume(self.0.as_ptr()) };
+        check(rc)
+    }
+
+    /// Return the Mynewt device
+    pub fn as_ptr(&self) -> *mut os::os_dev {
+        self.0.as_ptr()
+    }
+}
+
+/// Open Mynewt device. Closed when dropped.
+#[derive(Debug)]
+pub struct Device(DeviceRef);
+
+impl Device {
+    /// Open the device named `name`, waiting up to `timeout`. Returns `SYS_ENODEV` if the device
+    /// doesn't exist or couldn't be opened, `SYS_EINVAL` if the name is not null-terminated.
+    pub fn open(name: &Strn, timeout: Duration) -> MynewtResult<Device> {
+        unsafe { Device::open_with_arg(name, timeout, core::ptr::null_mut()) }
+    }
+
+    /// Open the device named `name`, passing `arg` to the driver, e.g. the sensor or SPI settings.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be valid for the driver of the device.
+    pub unsafe fn open_with_arg(name: &Strn, timeout: Duration, arg: Ptr) -> MynewtResult<Device> {
+        if !name.is_null_terminated() { return Err(MynewtError::SYS_EINVAL); }
+        let dev = os::os_dev_open(
+            name.as_cstr() as *const ::cty::c_char,
+            timeout.to_ticks_ceil(),
+            arg
+        );
+        DeviceRef::from_ptr(dev)
+            .map(Device)
+            .ok_or(MynewtError::SYS_ENODEV)
+    }
+
+    /// Return the registered device, which stays valid after closing
+    pub fn device(&self) -> DeviceRef { self.0 }
+
+    /// Return the device name
+    pub fn name(&self) -> Strn<'static> { self.0.name() }
+
+    /// Return the device state
+    pub fn state(&self) -> DeviceState { self.0.state() }
+
+    /// Suspend the device until `until`. If `force` is true, the device is suspended even if the driver refuses.
+    pub fn suspend(&self, until: Instant, force: bool) -> MynewtResult<()> { self.0.suspend(until, force) }
+
+    /// Resume the suspended device
+    pub fn resume(&self) -> MynewtResult<()> { self.0.resume() }
+
+    /// Close the device, returning the error from the driver
+    pub fn close(self) -> MynewtResult<()> {
+        let dev = self.0;
+        core::mem::forget(self);
+        check(unsafe { os::os_dev_close(dev.as_ptr()) })
+    }
+
+    /// Return the Mynewt device
+    pub fn as_ptr(&self) -> *mut os::os_dev { self.0.as_ptr() }
+}
+
+/// Close the device
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe { os::os_dev_close(self.0.as_ptr()) };
+    }
+}
+
+/// Return an iterator over all registered devices, in initialisation order
+pub fn devices() -> Devices {
+    Devices { prev: None, done: false }
+}
+
+/// Iterator over all registered devices. Returned by `devices()`.
+pub struct Devices {
+    /// Device returned by the previous call to `next()`, or `None` to start from the first device
+    prev: Option<DeviceRef>,
+    /// True if all devices have been returned
+    done: bool,
+}
+
+/// State passed to `walk_callback()`
+struct WalkState {
+    /// Return the device after this device, or the first device if `None`
+    prev:  Option<DeviceRef>,
+    /// True if `prev` has been seen
+    found: bool,
+    /// Device after `prev`
+    next:  Option<DeviceRef>,
+}
+
+impl Iterator for Devices {
+    type Item = DeviceRef;
+
+    fn next(&mut self) -> Option<DeviceRef> {
+        if self.done { return None; }
+        let mut state = WalkState { prev: self.prev, found: self.prev.is_none(), next: None };
+        unsafe { os::os_dev_walk(Some(walk_callback), &mut state as *mut WalkState as Ptr) };
+        match state.next {
+            Some(dev) => { self.prev = Some(dev); Some(dev) }
+            None      => { self.done = true; None }
+        }
+    }
+}
+
+/// Called by `os_dev_walk()` for each device. Returns non-zero to stop walking when the device after `prev` is found.
+extern "C" fn walk_callback(dev: *mut os::os_dev, arg: Ptr) -> ::cty::c_int {
+    let state = unsafe { &mut *(arg as *mut WalkState) };
+    let dev = match unsafe { DeviceRef::from_ptr(dev) } {
+        Some(dev) => dev,
+        None => return 0,
+    };
+    if state.found {
+        state.next = Some(dev);
+        return 1;  //  Stop walking
+    }
+    if Some(dev) == state.prev { state.found = true; }
+    0
+}
+
+/// Stage of system initialisation when a device is initialised
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InitStage {
+    /// Before the OS starts, for devices needed by the kernel
+    Primary,
+    /// Before the OS starts, after the primary devices
+    Secondary,
+    /// After the OS starts
+    Kernel,
+}
+
+impl InitStage {
+    /// Return the Mynewt stage `OS_DEV_INIT_*`
+    fn to_os(self) -> u8 {
+        match self {
+            InitStage::Primary   => os::OS_DEV_INIT_PRIMARY as u8,
+            InitStage::Secondary => os::OS_DEV_INIT_SECONDARY as u8,
+            InitStage::Kernel    => os::OS_DEV_INIT_KERNEL as u8,
+        }
+    }
+}
+
+/// Driver for a device implemented in Rust. Each callback is called by Mynewt for the device
+/// registered by `DriverDevice::create()`. The default callbacks do nothing and succeed.
+pub trait DeviceDriver: Sync + 'static {
+    /// Initialise the device. Called once at the initialisation stage of the device.
+    fn init(&'static self, _dev: DeviceRef) -> MynewtResult<()> { Ok(()) }
+
+    /// Open the device, waiting up to `timeout`. `arg` is passed by the caller of `os_dev_open()`.
+    fn open(&'static self, _dev: DeviceRef, _timeout: Duration, _arg: Ptr) -> MynewtResult<()> { Ok(()) }
+
+    /// Close the device
+    fn close(&'static self, _dev: DeviceRef) -> MynewtResult<()> { Ok(()) }
+
+    /// Suspend the device until `until`. If `force` is true, the device is suspended even if this returns an error.
+    fn suspend(&'static self, _dev: DeviceRef, _until: Instant, _force: bool) -> MynewtResult<()> { Ok(()) }
+
+    /// Resume the suspended device
+    fn resume(&'static self, _dev: DeviceRef) -> MynewtResult<()> { Ok(()) }
+}
+
+/// Mynewt device implemented by the Rust driver `D`. Declare as `static` and call `create()` to register the device.
+#[repr(C)]
+pub struct DriverDevice<D: DeviceDriver> {
+    /// Mynewt device. Must be the first field, because the device callbacks convert the `os_dev` pointer to `DriverDevice<D>`.
+    dev:     UnsafeCell<os::os_dev>,
+    /// Rust driver for the device
+    driver:  D,
+    /// True if the device has been registered
+    created: AtomicBool,
+}
+
+/// Mynewt devices are registered once and used by multiple tasks
+unsafe impl<D: DeviceDriver> Sync for DriverDevice<D> {}
+
+impl<D: DeviceDriver> DriverDevice<D> {
+    /// Return an unregistered device for the driver. May be used in `static` declarations.
+    pub const fn new(driver: D) -> Self {
+        DriverDevice {
+            dev:     UnsafeCell::new(zeroed()),
+            driver,
+            created: AtomicBool::new(false),
+        }
+    }
+
+    /// Register the device named `name`, to be initialised at `stage` with `priority` (lowest first).
+    /// If the OS has started, the device is initialised now. Returns `SYS_EALREADY` if already registered,
+    /// `SYS_EINVAL` if the name is not null-terminated.
+    pub fn create(&'static self, name: &'static Strn<'static>, stage: InitStage, priority: u8) -> MynewtResult<DeviceRef> {
+        if !name.is_null_terminated() { return Err(MynewtError::SYS_EINVAL); }
+        if self.created.swap(true, Ordering::AcqRel) {
+            return Err(MynewtError::SYS_EALREADY);
+        }
+        let rc = unsafe { os::os_dev_create(
+            self.dev.get(),
+            name.as_cstr() as *const ::cty::c_char,
+            stage.to_os(),
+            priority,
+            Some(init_callback::<D>),
+            core::ptr::null_mut()
+        ) };
+        if rc != 0 {
+            self.created.store(false, Ordering::Release);
+            return Err(MynewtError::from_os(rc as os::os_error_t));
+        }
+        Ok(self.device())
+    }
+
+    /// Return the driver
+    pub fn driver(&'static self) -> &'static D {
+        &self.driver
+    }
+
+    /// Return the registered device
+    pub fn device(&'static self) -> DeviceRef {
+        DeviceRef(unsafe { NonNull::new_unchecked(self.dev.get()) })
+    }
+}
+
+/// Return the `DriverDevice<D>` and `DeviceRef` for the Mynewt device
+fn driver_device<D: DeviceDriver>(dev: *mut os::os_dev) -> (&'static DriverDevice<D>, DeviceRef) {
+    let driver_dev: &'static DriverDevice<D> = unsafe { &*(dev as *const DriverDevice<D>) };
+    (driver_dev, driver_dev.device())
+}
+
+/// Mynewt init callback for `DriverDevice<D>`. Sets the other callbacks, then calls the Rust driver.
+extern "C" fn init_callback<D: DeviceDriver>(dev: *mut os::os_dev, _arg: Ptr) -> ::cty::c_int {
+    unsafe {
+        (*dev).od_handlers = os::os_dev_handlers {
+            od_open:    Some(open_callback::<D>),
+            od_suspend: Some(suspend_callback::<D>),
+            od_resume:  Some(resume_callback::<D>),
+            od_close:   Some(close_callback::<D>),
+        };
+    }
+    let (driver_dev, dev) = driver_device::<D>(dev);
+    to_rc(driver_dev.driver.init(dev))
+}
+
+/// Mynewt open callback for `DriverDevice<D>`
+extern "C" fn open_callback<D: DeviceDriver>(dev: *mut os::os_dev, timeout: u32, arg: Ptr) -> ::cty::c_int {
+    let (driver_dev, dev) = driver_device::<D>(dev);
+    to_rc(driver_dev.driver.open(dev, Duration::from_ticks(timeout), arg))
+}
+
+/// Mynewt close callback for `DriverDevice<D>`
+extern "C" fn close_callback<D: DeviceDriver>(dev: *mut os::os_dev) -> ::cty::c_int {
+    let (driver_dev, dev) = driver_device::<D>(dev);
+    to_rc(driver_dev.driver.close(dev))
+}
+
+/// Mynewt suspend callback for `DriverDevice<D>`
+extern "C" fn suspend_callback<D: DeviceDriver>(dev: *mut os::os_dev, until: os::os_time_t, force: ::cty::c_int) -> ::cty::c_int {
+    let (driver_dev, dev) = driver_device::<D>(dev);
+    to_rc(driver_dev.driver.suspend(dev, Instant::from_ticks(until), force != 0))
+}
+
+/// Mynewt resume callback for `DriverDevice<D>`
+extern "C" fn resume_callback<D: DeviceDriver>(dev: *mut os::os_dev) -> ::cty::c_int {
+    let (driver_dev, dev) = driver_device::<D>(dev);
+    to_rc(driver_dev.driver.resume(dev))
+}
+
+/// Convert the driver result to a Mynewt return code
+fn to_rc(res: MynewtResult<()>) -> ::cty::c_int {
+    match res {
+        Ok(())   => 0,
+        Err(err) => err.code(),
+    }
+}
+
+/// Convert the Mynewt return code to a result
+fn check(rc: ::cty::c_int) -> MynewtResult<()> {
+    if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+//! Event Queues and Events for Mynewt, built on `os_eventq` and `os_event`. Both may be declared as `static`
+//! without `unsafe`. Each `Event<T>` carries a payload of type `T` that is passed to the Rust event handler:
+//! ```
+//! /// Event Queue for the display task
+//! static DISPLAY_QUEUE: EventQueue = EventQueue::new();
+//! /// Event that refreshes the display, with the number of refreshes as payload
+//! static REFRESH_EVENT: Event<AtomicU32> = Event::new(refresh_display, AtomicU32::new(0));
+//!
+//! fn refresh_display(count: &'static AtomicU32) { count.fetch_add(1, Ordering::Relaxed); ... }
+//!
+//! REFRESH_EVENT.post(&DISPLAY_QUEUE);  //  May be called by interrupt handlers
+//! loop { DISPLAY_QUEUE.run(); }        //  In the display task: Call the handler for each event
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+        time::Duration,
+    },
+    result::*,
+    util::zeroable::zeroed,
+};
+
+/// Max number of event queues that may be polled by `EventQueue::poll_any()`
+pub const MAX_POLL_QUEUES: usize = 8;
+
+/// Mynewt Event Queue. Events posted to the queue are processed by the task that calls `run()`, `get_no_wait()` or `poll()`.
+/// The queue is initialised when first used, so it must not be moved: declare as `static` or use `EventQueue::default_queue()`.
+#[repr(transparent)]
+pub struct EventQueue {
+    /// Mynewt event queue
+    queue: UnsafeCell<os::os_eventq>,
+}
+
+/// Mynewt event queues may be used by multiple tasks and interrupt handlers
+unsafe impl Sync for EventQueue {}
+
+impl EventQueue {
+    /// Return an event queue that will be initialised when first used. May be used in `static` declarations.
+    pub const fn new() -> Self {
+        EventQueue {
+            queue: UnsafeCell::new(zeroed()),
+        }
+    }
+
+    /// Return the default event queue, which is processed by the OS main task
+    pub fn default_queue() -> &'static EventQueue {
+        let queue = os::eventq_dflt_get().expect("eventq fail");
+        assert!(!queue.is_null(), "no dflt eventq");
+        unsafe { EventQueue::from_ptr(queue) }
+    }
+
+    /// Return the `EventQueue` for a Mynewt event queue, e.g. an event queue declared in C
+    ///
+    /// # Safety
+    ///
+    /// `queue` must be a valid event queue that is never moved or deallocated.
+    pub unsafe fn from_ptr(queue: *mut os::os_eventq) -> &'static EventQueue {
+        &*(queue as *const EventQueue)  //  `EventQueue` has the same layout as `os_eventq`
+    }
+
+    /// Post the event to the queue. Does nothing if the event is already queued. May be called by interrupt handlers.
+    pub fn put<T: Sync + 'static>(&'static self, event: &'static Event<T>) {
+        unsafe { os::os_eventq_put(self.as_ptr(), event.as_ptr()) };
+    }
+
+    /// Remove the event from the queue if it's queued
+    pub fn remove<T: Sync + 'static>(&'static self, event: &'static Event<T>) {
+        unsafe { os::os_eventq_remove(self.as_ptr(), event.as_ptr()) };
+    }
+
+    /// Wait for an event on the queue and call its handler. Usually called in a loop by the task that owns the queue.
+    pub fn run(&'static self) {
+        os::eventq_run(unsafe { &mut *self.as_ptr() })
+            .expect("eventq fail");
+    }
+
+    /// Return the next event from the queue without waiting, or `None` if the queue is empty.
+    /// Call `run()` on the returned event to call its handler.
+    pub fn get_no_wait(&'static self) -> Option<EventRef> {
+        let event = unsafe { os::os_eventq_get_no_wait(self.as_ptr()) };
+        EventRef::from_ptr(event)
+    }
+
+    /// Wait up to `timeout` for an event on the queue. Returns `None` if no event arrives in time.
+    /// Call `run()` on the returned event to call its handler.
+    pub fn poll(&'static self, timeout: Duration) -> Option<EventRef> {
+        let mut ptr = self.as_ptr();
+        let event = unsafe { os::os_eventq_poll(&mut ptr, 1, timeout.to_ticks_ceil()) };
+        EventRef::from_ptr(event)
+    }
+
+    /// Wait up to `timeout` for an event on any of the queues, which are searched in order.
+    /// Returns `None` if no event arrives in time. Up to `MAX_POLL_QUEUES` queues may be polled,
+    /// returns `SYS_EINVAL` if there are more.
+    pub fn poll_any(queues: &[&'static EventQueue], timeout: Duration) -> MynewtResult<Option<EventRef>> {
+        if queues.len() > MAX_POLL_QUEUES { return Err(MynewtError::SYS_EINVAL); }
+        let mut ptrs: [*mut os::os_eventq; MAX_POLL_QUEUES] = [core::ptr::null_mut(); MAX_POLL_QUEUES];
+        for (ptr, queue) in ptrs.iter_mut().zip(queues) {
+            *ptr = queue.as_ptr();
+        }
+        let event = unsafe { os::os_eventq_poll(
+            ptrs.as_mut_ptr(),
+            queues.len() as i32,
+            timeout.to_ticks_ceil()
+        ) };
+        Ok(EventRef::from_ptr(event))
+    }
+
+    /// Return the Mynewt event queue. Initialise the queue if it hasn't been initialised.
+    pub fn as_ptr(&self) -> *mut os::os_eventq {
+        let queue = self.queue.get();
+        //  Same check as `os_eventq_inited()`: Initialised queues point to the last event.
+        if unsafe { (*queue).evq_list.stqh_last.is_null() } {
+            //  Queue may be used by multiple tasks, so we lock out interrupts while initialising.
+            critical_section(|_cs| {
+                if unsafe { (*queue).evq_list.stqh_last.is_null() } {
+                    unsafe { os::os_eventq_init(queue) };
+                }
+            });
+        }
+        queue
+    }
+}
+
+/// Event that carries a payload of type `T`. When the event is taken off a queue by `EventQueue::run()`,
+/// the handler is called with the payload. Use atomics or `Mutex` in the payload for data that changes.
+#[repr(C)]
+pub struct Event<T: Sync + 'static> {
+    /// Mynewt event. Must be the first field, because the event callback converts the `os_event` pointer to `Event<T>`.
+    event:   UnsafeCell<os::os_event>,
+    /// Rust handler for the event
+    handler: fn(&'static T),
+    /// Payload passed to the handler
+    payload: T,
+}
+
+/// Mynewt events may be posted by multiple tasks and interrupt handlers
+unsafe impl<T: Sync + 'static> Sync for Event<T> {}
+
+impl<T: Sync + 'static> Event<T> {
+    /// Return an event that calls `handler(&payload)`. May be used in `static` declarations.
+    pub const fn new(handler: fn(&'static T), payload: T) -> Self {
+        Event {
+            event: UnsafeCell::new(os::os_event {
+                ev_queued: 0,
+                ev_cb:     Some(event_callback::<T>),
+                ev_arg:    core::ptr::null_mut(),
+                ev_next:   zeroed(),
+            }),
+            handler,
+            payload,
+        }
+    }
+
+    /// Post the event to the queue. Same as `queue.put(self)`. May be called by interrupt handlers.
+    pub fn post(&'static self, queue: &'static EventQueue) {
+        queue.put(self);
+    }
+
+    /// Return true if the event is waiting on a queue
+    pub fn is_queued(&self) -> bool {
+        unsafe { (*self.event.get()).ev_queued != 0 }
+    }
+
+    /// Return the payload
+    pub fn payload(&'static self) -> &'static T {
+        &self.payload
+    }
+
+    /// Return the Mynewt event
+    pub fn as_ptr(&self) -> *mut os::os_event {
+        self.event.get()
+    }
+}
+
+/// Mynewt callback for `Event<T>`. Calls the Rust handler with the payload.
+extern "C" fn event_callback<T: Sync + 'static>(event: *mut os::os_event) {
+    let event = unsafe { &*(event as *const Event<T>) };
+    (event.handler)(&event.payload);
+}
+
+/// Event taken off a queue by `EventQueue::get_no_wait()` or `EventQueue::poll()`.
+/// The event may be an `Event<T>` or an event created in C.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EventRef(*mut os::os_event);
+
+impl EventRef {
+    /// Return the event for the pointer, or `None` if null
+    fn from_ptr(event: *mut os::os_event) -> Option<EventRef> {
+        if event.is_null() { None }
+        else { Some(EventRef(event)) }
+    }
+
+    /// Call the event callback, like `os_eventq_run()`. For `Event<T>`, this calls the Rust handler.
+    pub fn run(self) {
+        if let Some(cb) = unsafe { (*self.0).ev_cb } {
+            unsafe { cb(self.0) };
+        }
+    }
+
+    /// Return the Mynewt event
+    pub fn as_ptr(&self) -> *mut os::os_event {
+        self.0
+    }
+}
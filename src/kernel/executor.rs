@@ -0,0 +1,439 @@
+//! Async/await executor for Mynewt, driven by event queues. Each `AsyncTask` runs a `Future` in the Mynewt task
+//! that processes its event queue: waking the future posts the task's event to the queue, and the future is polled
+//! when `EventQueue::run()` takes the event. Futures are stored in the `AsyncTask`, so no heap is needed.
+//! Enabled by the `async` feature:
+//! ```
+//! /// Async task that animates the watch face, with 256 bytes of storage for its future
+//! static ANIMATE_TASK: AsyncTask<256> = AsyncTask::new();
+//!
+//! async fn animate() {
+//!     loop {
+//!         spi_noblock_write_flush_async().await.expect("flush fail");
+//!         executor::sleep(Duration::from_millis(40)).await;
+//!     }
+//! }
+//!
+//! ANIMATE_TASK.spawn(&DISPLAY_QUEUE, animate()) ? ;
+//! loop { DISPLAY_QUEUE.run(); }  //  In the display task: Poll the async tasks when they are woken
+//! ```
+
+use core::{
+    cell::{ Cell, RefCell, UnsafeCell },
+    future::Future,
+    marker::{ PhantomData, PhantomPinned },
+    mem::MaybeUninit,
+    pin::Pin,
+    sync::atomic::{ AtomicPtr, AtomicU8, Ordering },
+    task::{ Context, Poll, RawWaker, RawWakerVTable, Waker },
+};
+use crate::{
+    kernel::{
+        os,
+        critical::{ critical_section, IrqMutex },
+        event::EventQueue,
+        time::{ Duration, Instant },
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr,
+};
+
+/// Max number of wakers that may wait on a `WakerList` at the same time
+pub const MAX_WAKERS: usize = 4;
+
+/// Max alignment of futures stored in an `AsyncTask`
+pub const MAX_FUTURE_ALIGN: usize = 8;
+
+/// Empty `WakerList`
+const NO_WAKERS: [Option<Waker>; MAX_WAKERS] = [NO_WAKER; MAX_WAKERS];
+/// Empty slot in a `WakerList`
+const NO_WAKER: Option<Waker> = None;
+
+/// Task has no future and may be spawned
+const IDLE: u8 = 0;
+/// Task is storing a new future
+const SPAWNING: u8 = 1;
+/// Task has a future that has not completed
+const RUNNING: u8 = 2;
+
+/// Part of `AsyncTask` that doesn't depend on the storage size. Wakers point to the header.
+#[repr(C)]
+struct TaskHeader {
+    /// Mynewt event that polls the future. Must be the first field, because the event callback converts
+    /// the `os_event` pointer to `TaskHeader`.
+    event:  UnsafeCell<os::os_event>,
+    /// Mynewt event queue that the event is posted to
+    queue:  AtomicPtr<os::os_eventq>,
+    /// `IDLE`, `SPAWNING` or `RUNNING`
+    state:  AtomicU8,
+    /// Polls the future in the storage, and drops it when complete
+    poll:   UnsafeCell<Option<unsafe fn(*mut u8, &mut Context) -> Poll<()>>>,
+    /// Storage of the future
+    future: UnsafeCell<*mut u8>,
+}
+
+/// Checks at compile time that a future of type `F` fits into the storage of an `AsyncTask<SIZE>`
+struct FutureFits<F, const SIZE: usize>(PhantomData<F>);
+
+impl<F, const SIZE: usize> FutureFits<F, SIZE> {
+    /// Fails to compile if the future is too big or too aligned: The out-of-bounds index is reported when `spawn()` is compiled.
+    const CHECK: () = [()][(core::mem::size_of::<F>() > SIZE || core::mem::align_of::<F>() > MAX_FUTURE_ALIGN) as usize];
+}
+
+/// Storage for a future of up to `SIZE` bytes
+#[repr(C, align(8))]  //  Same as `MAX_FUTURE_ALIGN`
+struct Storage<const SIZE: usize>([u8; SIZE]);
+
+/// Async task that runs a future of up to `SIZE` bytes in the Mynewt task that processes its event queue.
+/// Declare as `static` and call `spawn()`. When the future completes, the task may be spawned again.
+#[repr(C)]
+pub struct AsyncTask<const SIZE: usize> {
+    /// Event, queue and state of the task
+    header:  TaskHeader,
+    /// Future being run
+    storage: UnsafeCell<MaybeUninit<Storage<SIZE>>>,
+}
+
+/// Async tasks may be spawned and woken by multiple tasks and interrupt handlers
+unsafe impl<const SIZE: usize> Sync for AsyncTask<SIZE> {}
+
+impl<const SIZE: usize> AsyncTask<SIZE> {
+    /// Return an idle async task. May be used in `static` declarations.
+    pub const fn new() -> Self {
+        AsyncTask {
+            header: TaskHeader {
+                event: UnsafeCell::new(os::os_event {
+                    ev_queued: 0,
+                    ev_cb:     Some(task_callback),
+                    ev_arg:    core::ptr::null_mut(),
+                    ev_next:   zeroed(),
+                }),
+                queue:  AtomicPtr::new(core::ptr::null_mut()),
+                state:  AtomicU8::new(IDLE),
+                poll:   UnsafeCell::new(None),
+                future: UnsafeCell::new(core::ptr::null_mut()),
+            },
+            storage: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Run `future` in the task that processes `queue`. The future is polled first by `queue`, then every time
+    /// it's woken. Returns `SYS_EBUSY` if the task is running a future. Futures larger than `SIZE` bytes
+    /// or aligned to more than `MAX_FUTURE_ALIGN` bytes fail to compile.
+    pub fn spawn<F>(&'static self, queue: &'static EventQueue, future: F) -> MynewtResult<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let () = FutureFits::<F, SIZE>::CHECK;
+        let header = &self.header;
+        if header.state.compare_exchange(IDLE, SPAWNING, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return Err(MynewtError::SYS_EBUSY);
+        }
+        //  Only this call may update the task, because the state is `SPAWNING`.
+        let storage = self.storage.get() as *mut u8;
+        unsafe {
+            (storage as *mut F).write(future);
+            *header.poll.get()   = Some(poll_future::<F>);
+            *header.future.get() = storage;
+        }
+        //  A wakeup from the previous future may be waiting on a different queue
+        let old_queue = header.queue.swap(queue.as_ptr(), Ordering::AcqRel);
+        if !old_queue.is_null() && old_queue != queue.as_ptr() {
+            unsafe { os::os_eventq_remove(old_queue, header.event.get()) };
+        }
+        header.state.store(RUNNING, Ordering::Release);
+        header.wake();  //  Poll the future for the first time
+        Ok(())
+    }
+
+    /// Return true if the task is running a future that has not completed
+    pub fn is_running(&self) -> bool {
+        self.header.state.load(Ordering::Acquire) != IDLE
+    }
+
+    /// Return the event queue of the task, or `None` if the task has never been spawned
+    pub fn event_queue(&self) -> Option<&'static EventQueue> {
+        let queue = self.header.queue.load(Ordering::Acquire);
+        if queue.is_null() { None }
+        else { Some(unsafe { EventQueue::from_ptr(queue) }) }
+    }
+}
+
+impl TaskHeader {
+    /// Post the task event to the queue, so that the future is polled. May be called by interrupt handlers.
+    fn wake(&self) {
+        let queue = self.queue.load(Ordering::Acquire);
+        if queue.is_null() { return; }
+        unsafe { os::os_eventq_put(queue, self.event.get()) };  //  Does nothing if already queued
+    }
+
+    /// Poll the future once. Called by the task that processes the queue.
+    fn poll(&'static self) {
+        if self.state.load(Ordering::Acquire) != RUNNING { return; }  //  Woken after completing
+        let waker = unsafe { Waker::from_raw(RawWaker::new(self as *const Self as *const (), &WAKER_VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        let (poll, future) = unsafe { (*self.poll.get(), *self.future.get()) };
+        let poll = poll.expect("no future");
+        //  Let the future find our queue with `current_queue()`, e.g. for `sleep()`
+        let polling = Polling {
+            task:  unsafe { os::os_sched_get_current_task() },
+            queue: self.queue.load(Ordering::Acquire),
+        };
+        let previous = critical_section(|cs| POLLING.borrow(cs).replace(polling));
+        let result = unsafe { poll(future, &mut cx) };
+        critical_section(|cs| POLLING.borrow(cs).set(previous));
+        if let Poll::Ready(()) = result {
+            self.state.store(IDLE, Ordering::Release);  //  Future has been dropped
+        }
+    }
+}
+
+/// Poll the future of type `F` stored at `future`. Drop the future when it completes.
+unsafe fn poll_future<F: Future<Output = ()>>(future: *mut u8, cx: &mut Context) -> Poll<()> {
+    let future = future as *mut F;
+    let result = Pin::new_unchecked(&mut *future).poll(cx);
+    if result.is_ready() {
+        core::ptr::drop_in_place(future);
+    }
+    result
+}
+
+/// Mynewt callback for `AsyncTask`. Polls the future.
+extern "C" fn task_callback(event: *mut os::os_event) {
+    let header: &'static TaskHeader = unsafe { &*(event as *const TaskHeader) };
+    header.poll();
+}
+
+/// Wakers for `AsyncTask`, which point to the `TaskHeader`. Tasks are `static`, so cloning and dropping do nothing.
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(waker_clone, waker_wake, waker_wake, waker_drop);
+
+/// Clone the waker for an `AsyncTask`
+unsafe fn waker_clone(header: *const ()) -> RawWaker {
+    RawWaker::new(header, &WAKER_VTABLE)
+}
+
+/// Wake the `AsyncTask`. May be called by interrupt handlers.
+unsafe fn waker_wake(header: *const ()) {
+    (*(header as *const TaskHeader)).wake();
+}
+
+/// Drop the waker for an `AsyncTask`
+unsafe fn waker_drop(_header: *const ()) {}
+
+/// `AsyncTask` being polled, set while its future is polled
+#[derive(Clone, Copy)]
+struct Polling {
+    /// Mynewt task that polls the future
+    task:  *mut os::os_task,
+    /// Event queue of the `AsyncTask`
+    queue: *mut os::os_eventq,
+}
+
+/// Pointers are only compared with the current task, and the queue is `static`
+unsafe impl Send for Polling {}
+
+/// `AsyncTask` being polled. If a higher-priority Mynewt task polls an `AsyncTask` in the middle of our poll,
+/// it restores our entry when done.
+static POLLING: IrqMutex<Cell<Polling>> = IrqMutex::new(Cell::new(Polling {
+    task:  core::ptr::null_mut(),
+    queue: core::ptr::null_mut(),
+}));
+
+/// Return the event queue of the `AsyncTask` being polled by the current Mynewt task, or `None` if the caller
+/// is not polled by an `AsyncTask`. Used by futures that need an event queue, e.g. for a Mynewt callout.
+pub fn current_queue() -> Option<&'static EventQueue> {
+    let polling = critical_section(|cs| POLLING.borrow(cs).get());
+    if polling.queue.is_null() || polling.task != unsafe { os::os_sched_get_current_task() } { None }
+    else { Some(unsafe { EventQueue::from_ptr(polling.queue) }) }
+}
+
+/// List of wakers waiting for something, e.g. a semaphore token. Used for implementing futures.
+/// Up to `MAX_WAKERS` wakers may wait at the same time, more wakers are rejected by `register()`.
+pub struct WakerList {
+    /// Waiting wakers, oldest first. May be updated by interrupt handlers.
+    wakers: IrqMutex<RefCell<[Option<Waker>; MAX_WAKERS]>>,
+}
+
+impl WakerList {
+    /// Return an empty list. May be used in `static` declarations.
+    pub const fn new() -> Self {
+        WakerList {
+            wakers: IrqMutex::new(RefCell::new(NO_WAKERS)),
+        }
+    }
+
+    /// Add the waker to the list, unless it's already in the list. Returns `SYS_ENOMEM` if the list is full,
+    /// without evicting the waiting wakers. May be called by interrupt handlers.
+    pub fn register(&self, waker: &Waker) -> MynewtResult<()> {
+        self.wakers.lock(|wakers| {
+            if wakers.iter().flatten().any(|w| w.will_wake(waker)) { return Ok(()); }
+            match wakers.iter_mut().find(|w| w.is_none()) {
+                Some(slot) => { *slot = Some(waker.clone()); Ok(()) }
+                None => Err(MynewtError::SYS_ENOMEM),  //  Too many wakers waiting
+            }
+        })
+    }
+
+    /// Replace the wakers in the list by `waker`. Used by futures that own the list, which only need to wake
+    /// the async task that polled them last. May be called by interrupt handlers.
+    pub fn replace(&self, waker: &Waker) {
+        let old = self.wakers.lock(|wakers| {
+            if let Some(w) = &wakers[0] {
+                if w.will_wake(waker) { return None; }
+            }
+            let mut new = NO_WAKERS;
+            new[0] = Some(waker.clone());
+            Some(core::mem::replace(wakers, new))
+        });
+        drop(old);  //  Drop the old wakers after the critical section
+    }
+
+    /// Wake all wakers in the list and empty the list. May be called by interrupt handlers.
+    pub fn wake(&self) {
+        let wakers = self.wakers.lock(|wakers| core::mem::replace(wakers, NO_WAKERS));
+        for waker in IntoIterator::into_iter(wakers).flatten() {  //  Take the wakers by value
+            waker.wake();
+        }
+    }
+}
+
+/// Return a future that completes after `duration`. `Duration::FOREVER` never completes.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep::new(Instant::now().checked_add(duration))
+}
+
+/// Return a future that completes at `deadline`. Completes immediately if `deadline` has passed.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep::new(Some(deadline))
+}
+
+/// Future returned by `sleep()` and `sleep_until()`. Uses a Mynewt callout that posts to the event queue
+/// of the async task, or the default event queue if not polled by an `AsyncTask`.
+pub struct Sleep {
+    /// Time to complete, or `None` to sleep forever
+    deadline: Option<Instant>,
+    /// Mynewt callout that wakes the future at the deadline. Initialised when first polled.
+    callout:  UnsafeCell<os::os_callout>,
+    /// True if the callout has been initialised
+    started:  bool,
+    /// Wakers to be woken by the callout
+    wakers:   WakerList,
+    /// Callout points to the future, so the future must not move after it's polled
+    _pinned:  PhantomPinned,
+}
+
+/// Callout is not initialised until the future is pinned and polled, so the future may be sent before that
+unsafe impl Send for Sleep {}
+
+impl Sleep {
+    /// Return a future that completes at `deadline`
+    fn new(deadline: Option<Instant>) -> Self {
+        Sleep {
+            deadline,
+            callout: UnsafeCell::new(zeroed()),
+            started: false,
+            wakers:  WakerList::new(),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Return the time to complete, or `None` if the future never completes
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = unsafe { self.get_unchecked_mut() };  //  Callout will not be moved
+        let deadline = match this.deadline {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,  //  Sleep forever
+        };
+        let ticks = match deadline.ticks_since(Instant::now()) {
+            Some(ticks) if ticks > 0 => ticks,
+            _ => return Poll::Ready(()),  //  Deadline has passed
+        };
+        this.wakers.replace(cx.waker());
+        let callout = this.callout.get();
+        if !this.started {
+            this.started = true;
+            let queue = current_queue().unwrap_or_else(EventQueue::default_queue);
+            let wakers = &this.wakers as *const WakerList as Ptr;
+            unsafe { os::os_callout_init(callout, queue.as_ptr(), Some(sleep_callback), wakers) };
+        }
+        //  Restart the callout in case the tick count has changed since the last poll
+        let rc = unsafe { os::os_callout_reset(callout, ticks) };
+        assert_eq!(rc, 0, "sleep fail");
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    /// Stop the callout and remove its event from the queue
+    fn drop(&mut self) {
+        if self.started {
+            unsafe { os::os_callout_stop(self.callout.get()) };
+        }
+    }
+}
+
+/// Mynewt callback for `Sleep`. Wakes the async task.
+extern "C" fn sleep_callback(event: *mut os::os_event) {
+    let wakers = unsafe { &*((*event).ev_arg as *const WakerList) };
+    wakers.wake();
+}
+
+/// Return a future that completes with the output of `future`, or `OS_TIMEOUT` if `future` doesn't complete within `timeout`.
+/// On timeout, `future` is dropped. Some futures block while dropped, e.g. `sensor::read_async()` waits for the
+/// sensor manager to finish reading.
+pub fn with_timeout<F: Future>(timeout: Duration, future: F) -> Timeout<F> {
+    Timeout { future, sleep: sleep(timeout) }
+}
+
+/// Future returned by `with_timeout()`
+pub struct Timeout<F: Future> {
+    /// Future being run
+    future: F,
+    /// Completes when the timeout expires
+    sleep:  Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = MynewtResult<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };  //  Fields will not be moved
+        if let Poll::Ready(output) = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        match unsafe { Pin::new_unchecked(&mut this.sleep) }.poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(MynewtError::OS_TIMEOUT)),
+            Poll::Pending   => Poll::Pending,
+        }
+    }
+}
+
+/// Return a future that lets the other async tasks and events on the queue run, then completes
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// Future returned by `yield_now()`
+pub struct YieldNow {
+    /// True if the future has been polled once
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded { return Poll::Ready(()); }
+        self.yielded = true;
+        cx.waker().wake_by_ref();  //  Post the task event behind the queued events
+        Poll::Pending
+    }
+}
@@ -0,0 +1,173 @@
+//! Rust global allocator for Mynewt, built on `os_malloc()`, `os_free()` and `os_realloc()`.
+//! Enabled by the `alloc` feature, so that `alloc::vec::Vec`, `Box` and `String` may be used:
+//! ```
+//! extern crate alloc;
+//! let readings: alloc::vec::Vec<u16> = alloc::vec![1, 2, 3];
+//! heap::stats().print();  //  Show the allocation counters on the console
+//! ```
+//! Allocation failures are logged to the console before Rust aborts with an allocation error.
+
+use core::{
+    alloc::{ GlobalAlloc, Layout },
+    sync::atomic::{ AtomicUsize, Ordering },
+};
+use crate::{
+    kernel::os,
+    sys::console,
+};
+
+/// Alignment of blocks returned by `os_malloc()`. Larger alignments are handled by over-allocating.
+const MIN_ALIGN: usize = os::OS_ALIGNMENT as usize;
+
+/// Size of the header that stores the `os_malloc()` pointer for over-aligned blocks
+const HEADER_SIZE: usize = core::mem::size_of::<usize>();
+
+/// Rust global allocator that allocates from the Mynewt heap
+pub struct MynewtAllocator;
+
+/// Register the Mynewt heap as the Rust global allocator
+#[global_allocator]
+static ALLOCATOR: MynewtAllocator = MynewtAllocator;
+
+/// Number of successful allocations
+static ALLOCATIONS:   AtomicUsize = AtomicUsize::new(0);
+/// Number of deallocations
+static DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Number of failed allocations
+static FAILURES:      AtomicUsize = AtomicUsize::new(0);
+/// Number of bytes currently allocated, as requested by Rust
+static BYTES_IN_USE:  AtomicUsize = AtomicUsize::new(0);
+/// Highest number of bytes allocated at the same time
+static PEAK_BYTES:    AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for MynewtAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr =
+            if layout.align() <= MIN_ALIGN { os::os_malloc(layout.size()) as *mut u8 }
+            else { alloc_aligned(layout) };
+        if ptr.is_null() { alloc_failed(layout); }
+        else { count_alloc(layout.size()); }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if layout.align() <= MIN_ALIGN { os::os_free(ptr as *mut ::cty::c_void); }
+        else { dealloc_aligned(ptr); }
+        count_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.align() > MIN_ALIGN {
+            //  `os_realloc()` doesn't preserve the larger alignment, so we allocate and copy
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let new_ptr = self.alloc(new_layout);
+            if !new_ptr.is_null() {
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+            return new_ptr;
+        }
+        let new_ptr = os::os_realloc(ptr as *mut ::cty::c_void, new_size) as *mut u8;
+        if new_ptr.is_null() {  //  Old block is unchanged
+            alloc_failed(Layout::from_size_align_unchecked(new_size, layout.align()));
+            return new_ptr;
+        }
+        count_dealloc(layout.size());
+        count_alloc(new_size);
+        new_ptr
+    }
+}
+
+/// Allocate a block with alignment larger than `MIN_ALIGN`. The `os_malloc()` pointer is stored before the block.
+unsafe fn alloc_aligned(layout: Layout) -> *mut u8 {
+    let size = match layout.size().checked_add(layout.align() + HEADER_SIZE) {
+        Some(size) => size,
+        None => return core::ptr::null_mut(),
+    };
+    let raw = os::os_malloc(size) as usize;
+    if raw == 0 { return core::ptr::null_mut(); }
+    let aligned = (raw + HEADER_SIZE + layout.align() - 1) & !(layout.align() - 1);
+    (aligned as *mut usize).sub(1).write_unaligned(raw);
+    aligned as *mut u8
+}
+
+/// Free a block allocated by `alloc_aligned()`
+unsafe fn dealloc_aligned(ptr: *mut u8) {
+    let raw = (ptr as *mut usize).sub(1).read_unaligned();
+    os::os_free(raw as *mut ::cty::c_void);
+}
+
+/// Update the counters for an allocation of `size` bytes
+fn count_alloc(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let in_use = BYTES_IN_USE.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_BYTES.fetch_max(in_use, Ordering::Relaxed);
+}
+
+/// Update the counters for a deallocation of `size` bytes
+fn count_dealloc(size: usize) {
+    DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    BYTES_IN_USE.fetch_sub(size, Ordering::Relaxed);
+}
+
+/// Count and log the failed allocation. Rust aborts with an allocation error after this, except for `try_reserve()`.
+fn alloc_failed(layout: Layout) {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+    console::print("alloc fail size=");
+    console::printint(layout.size() as i32);
+    console::print(" align=");
+    console::printint(layout.align() as i32);
+    console::print(" in use=");
+    console::printint(BYTES_IN_USE.load(Ordering::Relaxed) as i32);
+    console::print("\n");
+    console::flush();
+}
+
+/// Allocation counters for the Mynewt heap, for finding leaks
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeapStats {
+    /// Number of successful allocations
+    pub allocations:   usize,
+    /// Number of deallocations
+    pub deallocations: usize,
+    /// Number of failed allocations
+    pub failures:      usize,
+    /// Number of bytes currently allocated, as requested by Rust
+    pub bytes_in_use:  usize,
+    /// Highest number of bytes allocated at the same time
+    pub peak_bytes:    usize,
+}
+
+impl HeapStats {
+    /// Return the number of blocks currently allocated
+    pub fn live_blocks(&self) -> usize {
+        self.allocations.saturating_sub(self.deallocations)
+    }
+
+    /// Display the counters on the console
+    pub fn print(&self) {
+        console::print("heap allocs=");  console::printint(self.allocations as i32);
+        console::print(" frees=");       console::printint(self.deallocations as i32);
+        console::print(" fails=");       console::printint(self.failures as i32);
+        console::print(" in use=");      console::printint(self.bytes_in_use as i32);
+        console::print(" peak=");        console::printint(self.peak_bytes as i32);
+        console::print("\n");
+        console::flush();
+    }
+}
+
+/// Return the allocation counters. Call twice and compare `live_blocks()` to find leaks.
+pub fn stats() -> HeapStats {
+    HeapStats {
+        allocations:   ALLOCATIONS.load(Ordering::Relaxed),
+        deallocations: DEALLOCATIONS.load(Ordering::Relaxed),
+        failures:      FAILURES.load(Ordering::Relaxed),
+        bytes_in_use:  BYTES_IN_USE.load(Ordering::Relaxed),
+        peak_bytes:    PEAK_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Reset the peak to the number of bytes currently allocated
+pub fn reset_peak() {
+    PEAK_BYTES.store(BYTES_IN_USE.load(Ordering::Relaxed), Ordering::Relaxed);
+}
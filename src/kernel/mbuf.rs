@@ -0,0 +1,203 @@
+//! Owned Mbuf chains for Mynewt, built on `os_mbuf` and the MSYS mbuf pools.
+//! `Mbuf` frees its chain when dropped, so error paths don't need to call `os_mbuf_free_chain()`:
+//! ```
+//! let mut packet = Mbuf::new_pkthdr(64) ? ;  //  Allocate from MSYS
+//! packet.append(&[0x2c]) ? ;
+//! write!(packet, "temp={}", temp).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+//! for segment in packet.segments() { ... }   //  Contiguous data in each mbuf of the chain
+//! ```
+
+use core::{
+    fmt,
+    ptr::NonNull,
+};
+use crate::{
+    kernel::os,
+    result::*,
+};
+
+/// Chain of Mynewt mbufs, owned by Rust. The chain is freed when dropped.
+/// Use `into_raw()` to hand the chain over to C code, and `from_raw()` to take ownership of a chain from C code.
+pub struct Mbuf {
+    /// First mbuf of the chain
+    om: NonNull<os::os_mbuf>,
+}
+
+/// Mbufs may be passed between tasks, e.g. through `os_mqueue`
+unsafe impl Send for Mbuf {}
+
+impl Mbuf {
+    /// Allocate an mbuf with a packet header from MSYS, large enough for `size` bytes.
+    /// Returns `SYS_ENOMEM` if MSYS is out of mbufs.
+    pub fn new_pkthdr(size: u16) -> MynewtResult<Mbuf> {
+        let om = unsafe { os::os_msys_get_pkthdr(size, 0) };
+        unsafe { Mbuf::from_raw(om) }
+            .ok_or(MynewtError::SYS_ENOMEM)
+    }
+
+    /// Allocate an mbuf without a packet header from MSYS, large enough for `size` bytes,
+    /// with `leading_space` bytes reserved for prepending. Returns `SYS_ENOMEM` if MSYS is out of mbufs.
+    pub fn new(size: u16, leading_space: u16) -> MynewtResult<Mbuf> {
+        let om = unsafe { os::os_msys_get(size, leading_space) };
+        unsafe { Mbuf::from_raw(om) }
+            .ok_or(MynewtError::SYS_ENOMEM)
+    }
+
+    /// Allocate an mbuf chain with a packet header from MSYS and copy `data` into it
+    pub fn from_slice(data: &[u8]) -> MynewtResult<Mbuf> {
+        let size = if data.len() > u16::max_value() as usize { u16::max_value() }
+            else { data.len() as u16 };
+        let mut mbuf = Mbuf::new_pkthdr(size) ? ;
+        mbuf.append(data) ? ;
+        Ok(mbuf)
+    }
+
+    /// Take ownership of a Mynewt mbuf chain. Returns `None` if `om` is null.
+    ///
+    /// # Safety
+    ///
+    /// `om` must be the first mbuf of a valid chain that is not owned by anyone else.
+    pub unsafe fn from_raw(om: *mut os::os_mbuf) -> Option<Mbuf> {
+        NonNull::new(om).map(|om| Mbuf { om })
+    }
+
+    /// Give up ownership of the mbuf chain and return the first mbuf. The caller must free the chain.
+    pub fn into_raw(self) -> *mut os::os_mbuf {
+        let om = self.om.as_ptr();
+        core::mem::forget(self);
+        om
+    }
+
+    /// Return the first mbuf of the chain. The chain is still owned by this `Mbuf`.
+    pub fn as_ptr(&self) -> *mut os::os_mbuf {
+        self.om.as_ptr()
+    }
+
+    /// Return the number of data bytes in the chain
+    pub fn len(&self) -> usize {
+        unsafe { os::os_mbuf_len(self.om.as_ptr()) as usize }
+    }
+
+    /// Return true if the chain contains no data
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return true if the first mbuf has a packet header
+    pub fn has_pkthdr(&self) -> bool {
+        unsafe { self.om.as_ref().om_pkthdr_len as usize >= core::mem::size_of::<os::os_mbuf_pkthdr>() }
+    }
+
+    /// Copy `data` to the end of the chain, allocating mbufs from the chain's pool as needed.
+    /// Returns `SYS_ENOMEM` if the pool is out of mbufs.
+    pub fn append(&mut self, data: &[u8]) -> MynewtResult<()> {
+        for chunk in data.chunks(u16::max_value() as usize) {
+            let rc = unsafe { os::os_mbuf_append(
+                self.om.as_ptr(),
+                chunk.as_ptr() as *const ::cty::c_void,
+                chunk.len() as u16
+            ) };
+            if rc != 0 { return Err(MynewtError::SYS_ENOMEM); }  //  Mynewt returns `OS_ENOMEM`
+        }
+        Ok(())
+    }
+
+    /// Copy `data` to the front of the chain, allocating mbufs as needed.
+    /// Consumes the chain because Mynewt frees the chain if it runs out of mbufs, in which case `SYS_ENOMEM` is returned.
+    pub fn prepend(self, data: &[u8]) -> MynewtResult<Mbuf> {
+        assert!(data.len() <= i32::max_value() as usize, "mbuf overflow");
+        let om = unsafe { os::os_mbuf_prepend(self.into_raw(), data.len() as i32) };
+        let mbuf = unsafe { Mbuf::from_raw(om) }
+            .ok_or(MynewtError::SYS_ENOMEM) ? ;
+        //  Prepended bytes may span multiple mbufs, so we copy into the chain
+        let rc = unsafe { os::os_mbuf_copyinto(
+            mbuf.om.as_ptr(),
+            0,
+            data.as_ptr() as *const ::cty::c_void,
+            data.len() as i32
+        ) };
+        if rc != 0 { return Err(MynewtError::SYS_ENOMEM); }  //  Chain is too short, if the pool ran out
+        Ok(mbuf)
+    }
+
+    /// Trim `len` bytes from the front of the chain. If `len` is negative, trim `-len` bytes from the end.
+    /// Trims the entire chain if the chain is shorter.
+    pub fn adj(&mut self, len: i32) {
+        unsafe { os::os_mbuf_adj(self.om.as_ptr(), len) };
+    }
+
+    /// Rearrange the chain so that the first `len` bytes are contiguous in the first mbuf, i.e. in `data()`.
+    /// Consumes the chain because Mynewt frees the chain on failure, in which case `SYS_ENOMEM` is returned.
+    pub fn pullup(self, len: u16) -> MynewtResult<Mbuf> {
+        let om = unsafe { os::os_mbuf_pullup(self.into_raw(), len) };
+        unsafe { Mbuf::from_raw(om) }
+            .ok_or(MynewtError::SYS_ENOMEM)
+    }
+
+    /// Copy `dst.len()` bytes, starting at `offset` in the chain, into `dst`.
+    /// Returns `SYS_EINVAL` if the chain is too short.
+    pub fn copydata(&self, offset: usize, dst: &mut [u8]) -> MynewtResult<()> {
+        if offset.checked_add(dst.len()).map_or(true, |end| end > self.len()) {
+            return Err(MynewtError::SYS_EINVAL);
+        }
+        let rc = unsafe { os::os_mbuf_copydata(
+            self.om.as_ptr(),
+            offset as i32,
+            dst.len() as i32,
+            dst.as_mut_ptr() as *mut ::cty::c_void
+        ) };
+        if rc != 0 { return Err(MynewtError::SYS_EINVAL); }
+        Ok(())
+    }
+
+    /// Return the data in the first mbuf of the chain
+    pub fn data(&self) -> &[u8] {
+        segment(self.om.as_ptr())
+    }
+
+    /// Return an iterator over the contiguous data in each mbuf of the chain
+    pub fn segments(&self) -> Segments<'_> {
+        Segments { om: self.om.as_ptr(), _mbuf: self }
+    }
+}
+
+/// Free the mbuf chain
+impl Drop for Mbuf {
+    fn drop(&mut self) {
+        unsafe { os::os_mbuf_free_chain(self.om.as_ptr()) };
+    }
+}
+
+/// Append formatted text to the chain. Fails if the pool is out of mbufs.
+impl fmt::Write for Mbuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s.as_bytes())
+            .map_err(|_| fmt::Error)
+    }
+}
+
+/// Iterator over the contiguous data in each mbuf of a chain. Returned by `Mbuf::segments()`.
+pub struct Segments<'a> {
+    /// Next mbuf in the chain, or null if none
+    om:    *mut os::os_mbuf,
+    /// Chain being iterated
+    _mbuf: &'a Mbuf,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.om.is_null() { return None; }
+        let data = segment(self.om);
+        self.om = unsafe { (*self.om).om_next.sle_next };  //  Fetch next mbuf in the chain
+        Some(data)
+    }
+}
+
+/// Return the data in the mbuf
+fn segment<'a>(om: *mut os::os_mbuf) -> &'a [u8] {
+    let om = unsafe { &*om };
+    if om.om_len == 0 { return &[]; }
+    unsafe { core::slice::from_raw_parts(om.om_data, om.om_len as usize) }
+}
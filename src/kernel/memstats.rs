@@ -0,0 +1,197 @@
+//! Memory pool and MSYS statistics for Mynewt, with low-water alarms. Used to find out why the mbuf pools ran dry:
+//! ```
+//! /// Alarm that fires when MSYS has fewer than 4 free mbufs
+//! static MBUF_ALARM: LowWaterAlarm = LowWaterAlarm::new(Watch::Msys, 4, on_low_mbufs);
+//!
+//! fn on_low_mbufs(alarm: &'static LowWaterAlarm, num_free: usize) { memstats::print_mempools(); }
+//!
+//! MBUF_ALARM.register() ? ;
+//! memstats::start_monitor(Duration::from_secs(1)) ? ;  //  Check the alarms every second
+//! ```
+
+use core::{
+    fmt::{ self, Write },
+    sync::atomic::{ AtomicBool, AtomicPtr, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        pool::{ self, MempoolInfo },
+        time::Duration,
+        timer::Timer,
+    },
+    result::*,
+    sys::console,
+};
+
+/// Max number of low-water alarms that may be registered
+pub const MAX_ALARMS: usize = 8;
+
+/// Unused alarm slot, for initialising `ALARMS`. Each slot is a copy, the const itself is never modified.
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_ALARM: AtomicPtr<LowWaterAlarm> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Registered low-water alarms. Null for unused slots.
+static ALARMS: [AtomicPtr<LowWaterAlarm>; MAX_ALARMS] = [NO_ALARM; MAX_ALARMS];
+
+/// Timer that checks the registered alarms periodically
+static MONITOR_TIMER: Timer = Timer::new(monitor_handler);
+
+/// Return the total number of mbufs in the MSYS pools
+pub fn msys_count() -> usize {
+    unsafe { os::os_msys_count() as usize }
+}
+
+/// Return the number of free mbufs in the MSYS pools
+pub fn msys_num_free() -> usize {
+    unsafe { os::os_msys_num_free() as usize }
+}
+
+/// Return the stats for the memory pool named `name`, or `None` if there is no such pool
+pub fn find_mempool(name: &str) -> Option<MempoolInfo> {
+    pool::mempools()
+        .find(|info| info.name() == name)
+}
+
+/// Memory to be watched by a `LowWaterAlarm`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Watch {
+    /// Free blocks in the memory pool with this name
+    Pool(&'static str),
+    /// Free mbufs in all MSYS pools
+    Msys,
+}
+
+impl Watch {
+    /// Return the number of free blocks, or `None` if the pool doesn't exist
+    fn num_free(&self) -> Option<usize> {
+        match self {
+            Watch::Pool(name) => find_mempool(name).map(|info| info.num_free()),
+            Watch::Msys       => Some(msys_num_free()),
+        }
+    }
+}
+
+/// Alarm that calls a Rust handler when the number of free blocks falls below a threshold.
+/// The handler is called once when the threshold is crossed, and again only after the free blocks
+/// have recovered to the threshold. Declare as `static` and call `register()`.
+pub struct LowWaterAlarm {
+    /// Memory being watched
+    watch:     Watch,
+    /// Alarm fires when the number of free blocks is below this threshold
+    threshold: usize,
+    /// Rust handler, called with the number of free blocks
+    handler:   fn(&'static LowWaterAlarm, usize),
+    /// True if the alarm has fired and the free blocks have not recovered
+    triggered: AtomicBool,
+}
+
+impl LowWaterAlarm {
+    /// Return an alarm that calls `handler` when `watch` has fewer than `threshold` free blocks.
+    /// May be used in `static` declarations.
+    pub const fn new(watch: Watch, threshold: usize, handler: fn(&'static LowWaterAlarm, usize)) -> Self {
+        LowWaterAlarm {
+            watch,
+            threshold,
+            handler,
+            triggered: AtomicBool::new(false),
+        }
+    }
+
+    /// Check the alarm when `check_alarms()` is called or the monitor timer expires.
+    /// Returns `SYS_EALREADY` if already registered, `SYS_ENOMEM` if `MAX_ALARMS` alarms are registered.
+    pub fn register(&'static self) -> MynewtResult<()> {
+        let ptr = self as *const LowWaterAlarm as *mut LowWaterAlarm;
+        if ALARMS.iter().any(|slot| slot.load(Ordering::Acquire) == ptr) {
+            return Err(MynewtError::SYS_EALREADY);
+        }
+        for slot in ALARMS.iter() {
+            if slot.compare_exchange(core::ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(MynewtError::SYS_ENOMEM)
+    }
+
+    /// Stop checking the alarm
+    pub fn unregister(&'static self) {
+        let ptr = self as *const LowWaterAlarm as *mut LowWaterAlarm;
+        for slot in ALARMS.iter() {
+            slot.compare_exchange(ptr, core::ptr::null_mut(), Ordering::AcqRel, Ordering::Acquire).ok();
+        }
+    }
+
+    /// Check the number of free blocks and call the handler if it has fallen below the threshold
+    pub fn check(&'static self) {
+        let num_free = match self.watch.num_free() {
+            Some(num_free) => num_free,
+            None => return,  //  Pool doesn't exist yet
+        };
+        if num_free >= self.threshold {
+            self.triggered.store(false, Ordering::Release);  //  Recovered, so we rearm the alarm
+        } else if !self.triggered.swap(true, Ordering::AcqRel) {
+            (self.handler)(self, num_free);
+        }
+    }
+
+    /// Return the memory being watched
+    pub fn watch(&self) -> Watch { self.watch }
+
+    /// Return the threshold
+    pub fn threshold(&self) -> usize { self.threshold }
+
+    /// Return true if the alarm has fired and the free blocks have not recovered
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::Acquire)
+    }
+}
+
+/// Check all registered alarms. Called by the monitor timer, may also be called after an allocation fails.
+pub fn check_alarms() {
+    for slot in ALARMS.iter() {
+        let ptr = slot.load(Ordering::Acquire);
+        if ptr.is_null() { continue; }
+        let alarm: &'static LowWaterAlarm = unsafe { &*ptr };
+        alarm.check();
+    }
+}
+
+/// Check all registered alarms every `period`, in the task that processes the default event queue
+pub fn start_monitor(period: Duration) -> MynewtResult<()> {
+    MONITOR_TIMER.start_periodic(period)
+}
+
+/// Stop checking the alarms periodically
+pub fn stop_monitor() {
+    MONITOR_TIMER.stop();
+}
+
+/// Called when the monitor timer expires
+fn monitor_handler(_timer: &'static Timer) {
+    check_alarms();
+}
+
+/// Table of all Mynewt memory pools and the MSYS totals, e.g. `write!(console::Console, "{}", MempoolTable)`
+pub struct MempoolTable;
+
+impl fmt::Display for MempoolTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "BLKSZ TOTAL  FREE   MIN NAME")?;
+        for info in pool::mempools() {
+            writeln!(f, "{:>5} {:>5} {:>5} {:>5} {}",
+                info.block_size(),
+                info.num_blocks(),
+                info.num_free(),
+                info.min_free(),
+                info.name()
+            )?;
+        }
+        writeln!(f, "msys {} free of {}", msys_num_free(), msys_count())
+    }
+}
+
+/// Display the table of all Mynewt memory pools and the MSYS totals on the console
+pub fn print_mempools() {
+    write!(console::Console, "{}", MempoolTable).ok();
+    console::flush();
+}
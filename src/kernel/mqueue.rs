@@ -0,0 +1,115 @@
+//! Mbuf Queues for Mynewt, built on `os_mqueue`. Packets put into an `MQueue` are passed one at a time
+//! to its Rust handler, which is called by the task that processes the paired event queue.
+//! Each `MQueue<T>` carries a context of type `T` that is passed to the handler with each packet:
+//! ```
+//! /// Event Queue for the network task
+//! static NETWORK_QUEUE: EventQueue = EventQueue::new();
+//! /// Mbuf Queue for received packets, with the number of packets received as context
+//! static RX_QUEUE: MQueue<AtomicU32> = MQueue::new(&NETWORK_QUEUE, handle_packet, AtomicU32::new(0));
+//!
+//! fn handle_packet(count: &'static AtomicU32, packet: Mbuf) { count.fetch_add(1, Ordering::Relaxed); ... }
+//!
+//! RX_QUEUE.put(Mbuf::from_slice(&data) ?) ? ;  //  May be called by interrupt handlers
+//! loop { NETWORK_QUEUE.run(); }                //  In the network task: Call the handler for each packet
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+        event::EventQueue,
+        mbuf::Mbuf,
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr,
+};
+
+/// Mynewt Mbuf Queue paired with an event queue. Owns the queued mbuf chains until they are passed to the handler.
+/// The queue is initialised when first used, so it must not be moved: declare as `static`.
+pub struct MQueue<T: Sync + 'static> {
+    /// Mynewt mbuf queue. Its event points to this `MQueue` when initialised.
+    mqueue:  UnsafeCell<os::os_mqueue>,
+    /// Event queue that is notified when packets are queued
+    queue:   &'static EventQueue,
+    /// Rust handler for each packet
+    handler: fn(&'static T, Mbuf),
+    /// Context passed to the handler
+    context: T,
+}
+
+/// Mynewt mbuf queues may be used by multiple tasks and interrupt handlers
+unsafe impl<T: Sync + 'static> Sync for MQueue<T> {}
+
+impl<T: Sync + 'static> MQueue<T> {
+    /// Return an mbuf queue that calls `handler(&context, packet)` for each packet, in the task that processes `queue`.
+    /// May be used in `static` declarations.
+    pub const fn new(queue: &'static EventQueue, handler: fn(&'static T, Mbuf), context: T) -> Self {
+        MQueue {
+            mqueue: UnsafeCell::new(zeroed()),
+            queue,
+            handler,
+            context,
+        }
+    }
+
+    /// Add the packet to the queue and notify the event queue. May be called by interrupt handlers.
+    /// Returns `SYS_EINVAL` and frees the packet if it has no packet header.
+    pub fn put(&'static self, packet: Mbuf) -> MynewtResult<()> {
+        if !packet.has_pkthdr() { return Err(MynewtError::SYS_EINVAL); }
+        let rc = unsafe { os::os_mqueue_put(self.as_ptr(), self.queue.as_ptr(), packet.as_ptr()) };
+        if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }  //  Packet is freed when dropped
+        packet.into_raw();  //  Mbuf queue now owns the packet
+        Ok(())
+    }
+
+    /// Remove and return the next packet without waiting, or `None` if the queue is empty.
+    /// Usually not needed, because the handler is called for each packet.
+    pub fn get(&'static self) -> Option<Mbuf> {
+        let om = unsafe { os::os_mqueue_get(self.as_ptr()) };
+        unsafe { Mbuf::from_raw(om) }
+    }
+
+    /// Return true if no packets are queued
+    pub fn is_empty(&self) -> bool {
+        unsafe { (*self.mqueue.get()).mq_head.stqh_first.is_null() }
+    }
+
+    /// Return the event queue that is notified when packets are queued
+    pub fn event_queue(&self) -> &'static EventQueue {
+        self.queue
+    }
+
+    /// Return the context
+    pub fn context(&'static self) -> &'static T {
+        &self.context
+    }
+
+    /// Return the Mynewt mbuf queue. Initialise the queue if it hasn't been initialised.
+    pub fn as_ptr(&'static self) -> *mut os::os_mqueue {
+        let mqueue = self.mqueue.get();
+        //  Initialised queues point to the last packet
+        if unsafe { (*mqueue).mq_head.stqh_last.is_null() } {
+            //  Queue may be used by multiple tasks, so we lock out interrupts while initialising.
+            let rc = critical_section(|_cs| {
+                if unsafe { !(*mqueue).mq_head.stqh_last.is_null() } { return 0; }  //  Initialised by another task
+                unsafe { os::os_mqueue_init(
+                    mqueue,
+                    Some(mqueue_callback::<T>),
+                    self as *const Self as Ptr
+                ) }
+            });
+            assert_eq!(rc, 0, "mqueue fail");
+        }
+        mqueue
+    }
+}
+
+/// Mynewt callback for `MQueue<T>`. Calls the Rust handler for each queued packet.
+extern "C" fn mqueue_callback<T: Sync + 'static>(event: *mut os::os_event) {
+    let mqueue: &'static MQueue<T> = unsafe { &*((*event).ev_arg as *const MQueue<T>) };
+    while let Some(packet) = mqueue.get() {
+        (mqueue.handler)(&mqueue.context, packet);
+    }
+}
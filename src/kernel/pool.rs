@@ -0,0 +1,275 @@
+//! Fixed-size typed memory pools for Mynewt, built on `os_mempool`. Allocation is deterministic and doesn't need a heap.
+//! Pools may be declared as `static` without `unsafe`. Each allocated block is returned as a `PoolBox<T>`,
+//! which drops the value and returns the block to the pool when dropped:
+//! ```
+//! /// Pool of 16 sensor samples
+//! static SAMPLE_POOL: Pool<SensorSample, 16> = Pool::new(&init_strn!("samples"));
+//!
+//! let sample = SAMPLE_POOL.alloc(SensorSample { ... }) ? ;  //  Fails with `SYS_ENOMEM` if all blocks are in use
+//! console::print("free samples: "); console::printint(SAMPLE_POOL.num_free() as i32); console::print("\n");
+//! for info in pool::mempools() { ... }  //  Stats for all Mynewt memory pools
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    mem::{ ManuallyDrop, MaybeUninit },
+    ops::{ Deref, DerefMut },
+    ptr::NonNull,
+};
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Strn, StrnBuf,
+};
+
+/// Max length of memory pool names returned by `os_mempool_info_get_next()`, including the terminating null
+const MEMPOOL_NAME_LEN: usize = os::OS_MEMPOOL_INFO_NAME_LEN as usize;
+
+/// Memory block of a pool. Holds a `T` when allocated, or the Mynewt free list pointer when free.
+/// The size is a multiple of the alignment, so every block in the array is aligned for `T` and `os_memblock`.
+#[repr(C)]
+union Block<T> {
+    /// Allocated value
+    value: ManuallyDrop<MaybeUninit<T>>,
+    /// Mynewt free list pointer
+    next:  *mut os::os_memblock,
+}
+
+/// Pool of `N` blocks, each holding a `T`. The pool is initialised and registered with Mynewt when first used,
+/// so it must not be moved: declare as `static`.
+pub struct Pool<T: Send + 'static, const N: usize> {
+    /// Mynewt memory pool
+    pool:   UnsafeCell<os::os_mempool>,
+    /// Memory blocks managed by Mynewt
+    blocks: UnsafeCell<MaybeUninit<[Block<T>; N]>>,
+    /// Name of the pool, shown in the memory pool stats
+    name:   &'static Strn<'static>,
+}
+
+/// Mynewt memory pools may be used by multiple tasks and interrupt handlers
+unsafe impl<T: Send + 'static, const N: usize> Sync for Pool<T, N> {}
+
+impl<T: Send + 'static, const N: usize> Pool<T, N> {
+    /// Fails to compile if `N` is 0 or too big for Mynewt: The out-of-bounds index is reported when `new()` is evaluated.
+    const SIZE_CHECK: () = [()][(N == 0 || N > u16::MAX as usize) as usize];
+
+    /// Return a pool of `N` blocks named `name`. May be used in `static` declarations.
+    /// `N` must be between 1 and 65535, checked at compile time.
+    pub const fn new(name: &'static Strn<'static>) -> Self {
+        let () = Self::SIZE_CHECK;
+        Pool {
+            pool:   UnsafeCell::new(zeroed()),
+            blocks: UnsafeCell::new(MaybeUninit::uninit()),
+            name,
+        }
+    }
+
+    /// Move `value` into a free block. Returns `SYS_ENOMEM` if all blocks are in use. May be called by interrupt handlers.
+    /// Returns the Mynewt error if the pool can't be initialised.
+    pub fn alloc(&'static self, value: T) -> MynewtResult<PoolBox<T>> {
+        self.init() ? ;
+        self.try_alloc(value)
+            .map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+
+    /// Move `value` into a free block. Returns `value` if all blocks are in use. May be called by interrupt handlers.
+    pub fn try_alloc(&'static self, value: T) -> Result<PoolBox<T>, T> {
+        let pool = self.as_ptr();
+        let block = unsafe { os::os_memblock_get(pool) } as *mut T;
+        match NonNull::new(block) {
+            Some(ptr) => {
+                unsafe { ptr.as_ptr().write(value) };
+                Ok(PoolBox { ptr, pool, _value: PhantomData })
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Return the number of blocks in the pool
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Return the number of free blocks
+    pub fn num_free(&'static self) -> usize {
+        unsafe { (*self.as_ptr()).mp_num_free as usize }
+    }
+
+    /// Return the lowest number of free blocks since the pool was initialised
+    pub fn min_free(&'static self) -> usize {
+        unsafe { (*self.as_ptr()).mp_min_free as usize }
+    }
+
+    /// Return the stats for the pool, as reported by `os_mempool_info_get_next()`.
+    /// Returns the Mynewt error if the pool can't be initialised.
+    pub fn stats(&'static self) -> MynewtResult<MempoolInfo> {
+        self.init() ? ;
+        let pool = self.pool.get();
+        mempools()
+            .find(|info| info.pool == pool)
+            .ok_or(MynewtError::SYS_ENOENT)  //  Pool is registered when initialised
+    }
+
+    /// Initialise the pool and register it with Mynewt, if it hasn't been initialised.
+    /// Called when the pool is first used. Returns the Mynewt error if the pool can't be initialised.
+    pub fn init(&'static self) -> MynewtResult<()> {
+        let pool = self.pool.get();
+        //  Initialised pools have a non-zero block size
+        if unsafe { (*pool).mp_block_size != 0 } { return Ok(()); }
+        self.name.validate();  //  Fail if the name is not null-terminated
+        //  Pool may be used by multiple tasks, so we lock out interrupts while initialising.
+        let rc = critical_section(|_cs| {
+            if unsafe { (*pool).mp_block_size != 0 } { return 0; }  //  Initialised by another task
+            unsafe { os::os_mempool_init(
+                pool,
+                N as u16,
+                core::mem::size_of::<Block<T>>() as u32,
+                self.blocks.get() as *mut ::cty::c_void,
+                self.name.as_cstr() as *mut ::cty::c_char
+            ) }
+        });
+        if rc != 0 { return Err(MynewtError::from_os(rc)); }
+        Ok(())
+    }
+
+    /// Return the Mynewt memory pool. Initialise and register the pool if it hasn't been initialised.
+    /// If the pool can't be initialised, it has no free blocks.
+    pub fn as_ptr(&'static self) -> *mut os::os_mempool {
+        self.init().ok();
+        self.pool.get()
+    }
+}
+
+/// Value allocated from a `Pool`. Drops the value and returns the block to the pool when dropped.
+pub struct PoolBox<T: Send + 'static> {
+    /// Allocated value
+    ptr:    NonNull<T>,
+    /// Pool that owns the block
+    pool:   *mut os::os_mempool,
+    /// `PoolBox` owns the value
+    _value: PhantomData<T>,
+}
+
+/// Values may be passed between tasks, and pools may be used by multiple tasks
+unsafe impl<T: Send + 'static> Send for PoolBox<T> {}
+/// Shared references only allow shared access to the value
+unsafe impl<T: Send + Sync + 'static> Sync for PoolBox<T> {}
+
+impl<T: Send + 'static> Deref for PoolBox<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: Send + 'static> DerefMut for PoolBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+/// Drop the value and return the block to the pool
+impl<T: Send + 'static> Drop for PoolBox<T> {
+    fn drop(&mut self) {
+        unsafe { core::ptr::drop_in_place(self.ptr.as_ptr()) };
+        let rc = unsafe { os::os_memblock_put(self.pool, self.ptr.as_ptr() as *mut ::cty::c_void) };
+        assert_eq!(rc, 0, "mempool fail");
+    }
+}
+
+impl<T: Send + fmt::Debug + 'static> fmt::Debug for PoolBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Send + fmt::Display + 'static> fmt::Display for PoolBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Stats for a Mynewt memory pool, returned by `os_mempool_info_get_next()`
+#[derive(Clone, Copy, Debug)]
+pub struct MempoolInfo {
+    /// Mynewt memory pool
+    pool:       *mut os::os_mempool,
+    /// Name of the pool
+    name:       StrnBuf<MEMPOOL_NAME_LEN>,
+    /// Size of each block in bytes
+    block_size: usize,
+    /// Number of blocks in the pool
+    num_blocks: usize,
+    /// Number of free blocks
+    num_free:   usize,
+    /// Lowest number of free blocks since the pool was initialised
+    min_free:   usize,
+}
+
+impl MempoolInfo {
+    /// Return the name of the pool
+    pub fn name(&self) -> Strn<'_> {
+        self.name.as_strn()
+    }
+
+    /// Return the size of each block in bytes
+    pub fn block_size(&self) -> usize { self.block_size }
+
+    /// Return the number of blocks in the pool
+    pub fn num_blocks(&self) -> usize { self.num_blocks }
+
+    /// Return the number of free blocks
+    pub fn num_free(&self) -> usize { self.num_free }
+
+    /// Return the number of blocks in use
+    pub fn num_used(&self) -> usize { self.num_blocks.saturating_sub(self.num_free) }
+
+    /// Return the lowest number of free blocks since the pool was initialised
+    pub fn min_free(&self) -> usize { self.min_free }
+
+    /// Return the Mynewt memory pool
+    pub fn as_ptr(&self) -> *mut os::os_mempool { self.pool }
+}
+
+/// Return an iterator over the stats of all registered Mynewt memory pools, including MSYS mbuf pools
+pub fn mempools() -> Mempools {
+    Mempools { prev: core::ptr::null_mut(), done: false }
+}
+
+/// Iterator over the stats of all registered Mynewt memory pools. Returned by `mempools()`.
+pub struct Mempools {
+    /// Pool returned by the previous call to `os_mempool_info_get_next()`, or null to start from the first pool
+    prev: *mut os::os_mempool,
+    /// True if all pools have been returned
+    done: bool,
+}
+
+impl Iterator for Mempools {
+    type Item = MempoolInfo;
+
+    fn next(&mut self) -> Option<MempoolInfo> {
+        if self.done { return None; }
+        let mut omi: os::os_mempool_info = zeroed();
+        let pool = unsafe { os::os_mempool_info_get_next(self.prev, &mut omi) };
+        if pool.is_null() { self.done = true; return None; }
+        self.prev = pool;
+        let mut name = StrnBuf::new();
+        let len = omi.omi_name.iter().position(|&c| c == 0).unwrap_or(omi.omi_name.len());
+        let bytes = unsafe { core::slice::from_raw_parts(omi.omi_name.as_ptr() as *const u8, len) };
+        name.push_bytes(&bytes[..len.min(name.capacity())]).ok();
+        Some(MempoolInfo {
+            pool,
+            name,
+            block_size: omi.omi_block_size as usize,
+            num_blocks: omi.omi_num_blocks as usize,
+            num_free:   omi.omi_num_free as usize,
+            min_free:   omi.omi_min_free as usize,
+        })
+    }
+}
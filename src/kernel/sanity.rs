@@ -0,0 +1,155 @@
+//! Sanity checks for Mynewt, built on the OS sanity task. A task or subsystem that doesn't check in within
+//! its interval is considered hung: its name is shown on the console, then Mynewt resets the system.
+//! The sanity task also tickles the hardware watchdog, so Rust tasks don't need to.
+//! ```
+//! /// Sanity check for the sensor subsystem, which is healthy while samples keep arriving
+//! static SENSOR_CHECK: SanityCheck = SanityCheck::new(&init_strn!("sensor"), Some(sensor_healthy));
+//!
+//! SENSOR_CHECK.register(Duration::from_secs(10)) ? ;
+//! SENSOR_CHECK.checkin() ? ;  //  Check in before 10 seconds have passed
+//!
+//! //  Tasks declare their interval when started, and call `sanity::checkin()` in their loop
+//! SPI_TASK.start_with_sanity(&init_strn!("spi"), 10, spi_task_func, (), SanityConfig::new(Duration::from_secs(60))) ? ;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{ AtomicBool, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        time::Duration,
+    },
+    result::*,
+    sys::console,
+    util::zeroable::zeroed,
+    Ptr, Strn,
+};
+
+/// Sanity settings for a task: the check-in interval and an optional health check.
+/// If the health check returns true, the task is considered sane even if it hasn't checked in.
+/// Health checks are functions, not closures, because `Task` and `SanityCheck` are created by `const fn`
+/// in `static` declarations, and our Rust toolchain doesn't allow trait objects like `&dyn Fn` in `const fn`.
+/// Keep any state needed by the health check in a `static`, e.g. an atomic counter.
+#[derive(Clone, Copy)]
+pub struct SanityConfig {
+    /// Max time between check-ins
+    interval: Duration,
+    /// Optional health check, called by the sanity task
+    health:   Option<fn() -> bool>,
+}
+
+impl SanityConfig {
+    /// Return the settings for a task that must check in every `interval`
+    pub const fn new(interval: Duration) -> Self {
+        SanityConfig { interval, health: None }
+    }
+
+    /// Return the settings with the health check `health`, called by the sanity task
+    pub const fn with_health(self, health: fn() -> bool) -> Self {
+        SanityConfig { interval: self.interval, health: Some(health) }
+    }
+
+    /// Return the max time between check-ins
+    pub fn interval(&self) -> Duration { self.interval }
+
+    /// Return the health check
+    pub fn health(&self) -> Option<fn() -> bool> { self.health }
+}
+
+/// Check in the current task with the sanity task. The task must have been started with a sanity interval.
+pub fn checkin() -> MynewtResult<()> {
+    let rc = unsafe { os::os_sanity_task_checkin(core::ptr::null_mut()) };
+    if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+    Ok(())
+}
+
+/// Sanity check for a subsystem that is not a task. Declare as `static` and call `register()`.
+/// Sanity checks can't be unregistered, so the subsystem must keep checking in.
+pub struct SanityCheck {
+    /// Mynewt sanity check
+    check:      UnsafeCell<os::os_sanity_check>,
+    /// Name shown on the console when the check fails
+    name:       &'static Strn<'static>,
+    /// Optional health check, called by the sanity task
+    health:     Option<fn() -> bool>,
+    /// True if the check has been registered
+    registered: AtomicBool,
+}
+
+/// Sanity checks are registered once and checked in by multiple tasks
+unsafe impl Sync for SanityCheck {}
+
+impl SanityCheck {
+    /// Return a sanity check named `name` with an optional health check. May be used in `static` declarations.
+    /// The health check is a function, not a closure, see `SanityConfig`.
+    pub const fn new(name: &'static Strn<'static>, health: Option<fn() -> bool>) -> Self {
+        SanityCheck {
+            check: UnsafeCell::new(zeroed()),
+            name,
+            health,
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// Start checking that `checkin()` is called at least every `interval`. Returns `SYS_EALREADY` if already registered,
+    /// `SYS_EINVAL` if the interval is zero or the name is not null-terminated.
+    pub fn register(&'static self, interval: Duration) -> MynewtResult<()> {
+        let ticks = interval.to_ticks();
+        if ticks == 0 || ticks == os::OS_TIMEOUT_NEVER { return Err(MynewtError::SYS_EINVAL); }
+        if !self.name.is_null_terminated() { return Err(MynewtError::SYS_EINVAL); }
+        if self.registered.swap(true, Ordering::AcqRel) {
+            return Err(MynewtError::SYS_EALREADY);
+        }
+        //  Only this call may update the check, because `registered` has been set.
+        let check = self.check.get();
+        let rc = unsafe { os::os_sanity_check_init(check) };
+        if rc != 0 { self.registered.store(false, Ordering::Release); return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        unsafe {
+            (*check).sc_checkin_itvl = ticks;
+            (*check).sc_func = Some(sanity_callback);
+            (*check).sc_arg  = self as *const Self as Ptr;
+        }
+        let rc = unsafe { os::os_sanity_check_register(check) };
+        if rc != 0 { self.registered.store(false, Ordering::Release); return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        Ok(())
+    }
+
+    /// Check in with the sanity task
+    pub fn checkin(&'static self) -> MynewtResult<()> {
+        if !self.registered.load(Ordering::Acquire) { return Err(MynewtError::SYS_EINVAL); }
+        let rc = unsafe { os::os_sanity_check_reset(self.check.get()) };
+        if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        Ok(())
+    }
+
+    /// Return the name of the check
+    pub fn name(&self) -> &'static Strn<'static> {
+        self.name
+    }
+}
+
+/// Mynewt callback for `SanityCheck`, called by the sanity task
+extern "C" fn sanity_callback(check: *mut os::os_sanity_check, arg: Ptr) -> ::cty::c_int {
+    let sanity = unsafe { &*(arg as *const SanityCheck) };
+    run_check(check, sanity.name, sanity.health)
+}
+
+/// Run the health check for the sanity check named `name`. Returns 0 if healthy.
+/// Otherwise returns non-zero so that Mynewt checks the interval, and shows the name on the console
+/// if the check-in is overdue, because Mynewt will reset the system.
+pub(crate) fn run_check(check: *mut os::os_sanity_check, name: &Strn, health: Option<fn() -> bool>) -> ::cty::c_int {
+    if let Some(health) = health {
+        if health() { return 0; }
+    }
+    let (last, interval) = unsafe { ((*check).sc_checkin_last, (*check).sc_checkin_itvl) };
+    let overdue = unsafe { os::os_time_get() }.wrapping_sub(last.wrapping_add(interval));
+    if overdue as i32 > 0 {  //  Same check as Mynewt, which will reset the system
+        console::print("sanity fail: ");
+        console::print_strn(name);
+        console::print("\n");
+        console::flush();
+    }
+    os::os_error_OS_ERROR as ::cty::c_int
+}
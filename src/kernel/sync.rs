@@ -0,0 +1,254 @@
+//! Semaphore and Mutex for Mynewt, built on `os_sem` and `os_mutex`. Both may be declared as `static`
+//! without `unsafe`:
+//! ```
+//! /// Semaphore that throttles the number of queued SPI requests
+//! static SPI_THROTTLE_SEM: Semaphore = Semaphore::new(2);
+//! /// Counter protected by a mutex
+//! static COUNTER: Mutex<u32> = Mutex::new(0);
+//!
+//! SPI_THROTTLE_SEM.pend(Duration::from_secs(30)) ? ;
+//! *COUNTER.lock(Duration::FOREVER) ? += 1;  //  Mutex is released at the end of the statement
+//! SPI_THROTTLE_SEM.pend_async().await ? ;   //  With the `async` feature: Wait without blocking the executor
+//! *COUNTER.lock_async().await ? += 1;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{ Deref, DerefMut },
+};
+#[cfg(feature = "async")]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{ Context, Poll },
+};
+#[cfg(feature = "async")]
+use crate::kernel::executor::WakerList;
+use crate::{
+    kernel::{
+        os,
+        time::Duration,
+    },
+    result::*,
+    util::zeroable::zeroed,
+};
+
+/// Convert the `os_error_t` returned by a semaphore or mutex call to `MynewtResult`
+fn check(rc: os::os_error_t) -> MynewtResult<()> {
+    if rc == os::os_error_OS_OK { Ok(()) }
+    else { Err(MynewtError::from_os(rc)) }
+}
+
+/// Counting semaphore. `pend()` takes a token, waiting until a token is available. `release()` returns a token.
+pub struct Semaphore {
+    /// Mynewt semaphore
+    sem: UnsafeCell<os::os_sem>,
+    /// Async tasks waiting for a token
+    #[cfg(feature = "async")]
+    waiters: WakerList,
+}
+
+/// Mynewt semaphores may be pended and released by multiple tasks. `release()` may be called by interrupt handlers.
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    /// Return a semaphore with `tokens` tokens. May be used in `static` declarations.
+    /// Equivalent to calling `os_sem_init()`.
+    pub const fn new(tokens: u16) -> Self {
+        Semaphore {
+            sem: UnsafeCell::new(os::os_sem {
+                sem_head:   zeroed(),  //  No tasks waiting
+                _pad:       0,
+                sem_tokens: tokens,
+            }),
+            #[cfg(feature = "async")]
+            waiters: WakerList::new(),
+        }
+    }
+
+    /// Wait up to `timeout` for a token and take it. Returns `OS_TIMEOUT` if no token is available in time.
+    /// `Duration::ZERO` returns immediately, `Duration::FOREVER` waits forever.
+    pub fn pend(&self, timeout: Duration) -> MynewtResult<()> {
+        let rc = unsafe { os::os_sem_pend(self.sem.get(), timeout.to_ticks_ceil()) };
+        check(rc)
+    }
+
+    /// Take a token if available, without waiting. Returns `OS_TIMEOUT` if no token is available.
+    pub fn try_pend(&self) -> MynewtResult<()> {
+        self.pend(Duration::ZERO)
+    }
+
+    /// Return a token to the semaphore and wake up the highest priority task waiting for it.
+    /// May be called by interrupt handlers.
+    pub fn release(&self) -> MynewtResult<()> {
+        let rc = unsafe { os::os_sem_release(self.sem.get()) };
+        #[cfg(feature = "async")]
+        self.waiters.wake();  //  Waiting async tasks will try to take the token
+        check(rc)
+    }
+
+    /// Return a future that completes when a token has been taken. The async task is not blocked while waiting.
+    /// Use `executor::with_timeout()` to wait with a timeout. Completes with `SYS_ENOMEM` if `executor::MAX_WAKERS`
+    /// other async tasks are waiting for the semaphore.
+    #[cfg(feature = "async")]
+    pub fn pend_async(&self) -> Pend<'_> {
+        Pend { sem: self }
+    }
+
+    /// Return the number of tokens available
+    pub fn tokens(&self) -> u16 {
+        unsafe { (*self.sem.get()).sem_tokens }
+    }
+
+    /// Return the Mynewt semaphore
+    pub fn as_ptr(&self) -> *mut os::os_sem {
+        self.sem.get()
+    }
+}
+
+/// Future returned by `Semaphore::pend_async()`
+#[cfg(feature = "async")]
+pub struct Pend<'a> {
+    /// Semaphore to take a token from
+    sem: &'a Semaphore,
+}
+
+#[cfg(feature = "async")]
+impl Future for Pend<'_> {
+    type Output = MynewtResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.sem.try_pend().is_ok() { return Poll::Ready(Ok(())); }
+        if let Err(err) = self.sem.waiters.register(cx.waker()) { return Poll::Ready(Err(err)); }
+        //  Token may have been released before we registered
+        if self.sem.try_pend().is_ok() { return Poll::Ready(Ok(())); }
+        Poll::Pending
+    }
+}
+
+/// Mutex that protects data of type `T`. `lock()` returns a `MutexGuard` that derefs to the data
+/// and releases the mutex when dropped. The mutex raises the priority of the owning task to avoid
+/// priority inversion. Must not be used by interrupt handlers.
+pub struct Mutex<T> {
+    /// Mynewt mutex
+    mutex: UnsafeCell<os::os_mutex>,
+    /// Data protected by the mutex
+    data:  UnsafeCell<T>,
+    /// Async tasks waiting for the mutex
+    #[cfg(feature = "async")]
+    waiters: WakerList,
+}
+
+/// Data is accessed only by the task that owns the mutex
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Return a mutex that protects `data`. May be used in `static` declarations.
+    /// Equivalent to calling `os_mutex_init()`.
+    pub const fn new(data: T) -> Self {
+        Mutex {
+            mutex: UnsafeCell::new(zeroed()),  //  No owner and no tasks waiting
+            data:  UnsafeCell::new(data),
+            #[cfg(feature = "async")]
+            waiters: WakerList::new(),
+        }
+    }
+
+    /// Wait up to `timeout` to lock the mutex. Returns a guard that releases the mutex when dropped.
+    /// Returns `OS_TIMEOUT` if the mutex is not available in time. Mynewt mutexes may be locked
+    /// again by the owning task, but that would create two guards for the same data, so we return `SYS_EBUSY` instead.
+    pub fn lock(&self, timeout: Duration) -> MynewtResult<MutexGuard<'_, T>> {
+        let current = unsafe { os::os_sched_get_current_task() };
+        let mutex = self.mutex.get();
+        if unsafe { (*mutex).mu_level } > 0 && unsafe { (*mutex).mu_owner } == current {
+            return Err(MynewtError::SYS_EBUSY);  //  Already locked by this task
+        }
+        let rc = unsafe { os::os_mutex_pend(mutex, timeout.to_ticks_ceil()) };
+        check(rc) ? ;
+        Ok(MutexGuard { mutex: self, _not_send: PhantomData })
+    }
+
+    /// Lock the mutex if available, without waiting. Returns `OS_TIMEOUT` if the mutex is locked by another task.
+    pub fn try_lock(&self) -> MynewtResult<MutexGuard<'_, T>> {
+        self.lock(Duration::ZERO)
+    }
+
+    /// Return a future that completes with a guard when the mutex has been locked. The async task is not blocked
+    /// while waiting. Use `executor::with_timeout()` to wait with a timeout. Completes with `SYS_ENOMEM` if
+    /// `executor::MAX_WAKERS` other async tasks are waiting for the mutex.
+    #[cfg(feature = "async")]
+    pub fn lock_async(&self) -> Lock<'_, T> {
+        Lock { mutex: self }
+    }
+
+    /// Return a mutable reference to the data. No locking is needed because we have exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Return the Mynewt mutex
+    pub fn as_ptr(&self) -> *mut os::os_mutex {
+        self.mutex.get()
+    }
+}
+
+/// Lock on a `Mutex`. Derefs to the protected data. Releases the mutex when dropped.
+pub struct MutexGuard<'a, T> {
+    /// Mutex that is locked
+    mutex: &'a Mutex<T>,
+    /// Guard must be dropped by the task that locked the mutex, so it can't be sent to another task
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    /// Return the protected data
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    /// Return the protected data for updating
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    /// Release the mutex
+    fn drop(&mut self) {
+        let rc = unsafe { os::os_mutex_release(self.mutex.mutex.get()) };
+        assert_eq!(rc, os::os_error_OS_OK, "mutex fail");  //  Only the owner may release the mutex
+        #[cfg(feature = "async")]
+        self.mutex.waiters.wake();  //  Waiting async tasks will try to lock the mutex
+    }
+}
+
+/// Future returned by `Mutex::lock_async()`
+#[cfg(feature = "async")]
+pub struct Lock<'a, T> {
+    /// Mutex to be locked
+    mutex: &'a Mutex<T>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MynewtResult<MutexGuard<'a, T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        match mutex.try_lock() {
+            //  Locked by another task, or by the Mynewt task that polls us, which releases it after we return
+            Err(MynewtError::OS_TIMEOUT) | Err(MynewtError::SYS_EBUSY) => {}
+            res => return Poll::Ready(res),
+        }
+        if let Err(err) = mutex.waiters.register(cx.waker()) { return Poll::Ready(Err(err)); }
+        //  Mutex may have been released before we registered
+        match mutex.try_lock() {
+            Err(MynewtError::OS_TIMEOUT) | Err(MynewtError::SYS_EBUSY) => Poll::Pending,
+            res => Poll::Ready(res),
+        }
+    }
+}
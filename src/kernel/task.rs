@@ -0,0 +1,374 @@
+//! Safe Task API for Mynewt. A `Task` contains the task object and the stack, so it can be declared as a
+//! `static` without `unsafe`:
+//! ```
+//! /// SPI Task with 256 words of stack
+//! static SPI_TASK: Task<(), 256> = Task::new();
+//!
+//! SPI_TASK.start(&init_strn!("spi"), 10, spi_task_func, ()) ? ;
+//! ```
+//! `tasks()` returns a snapshot of every Mynewt task, for finding tasks that are about to overflow their stacks:
+//! ```
+//! for task in kernel::tasks() {
+//!     if task.stack_free() < 64 { ... }
+//! }
+//! task::print_tasks();  //  Show a `top`-like table on the console
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{ self, Write },
+    sync::atomic::{ AtomicBool, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        sanity::{ self, SanityConfig },
+        time::{ self, Duration, Instant },
+    },
+    result::*,
+    sys::console,
+    util::zeroable::zeroed,
+    Ptr, Strn, StrnBuf,
+};
+
+/// Max length of task names returned by `os_task_info_get_next()`, including the terminating null
+const TASK_NAME_LEN: usize = 32;
+
+/// Mynewt task that runs the function `fn(T)` with a typed argument `T`, on a stack of `N` words (`os_stack_t`).
+/// Declare as `static` and call `start()` to start the task. Closures that don't capture any variables
+/// may be used as the task function, the argument carries any state needed by the task.
+pub struct Task<T: Send + 'static, const N: usize> {
+    /// Mynewt task object
+    task:    UnsafeCell<os::os_task>,
+    /// Stack space for the task
+    stack:   UnsafeCell<[os::os_stack_t; N]>,
+    /// Task function and argument. Taken by the task when it starts.
+    entry:   UnsafeCell<Option<(fn(T), T)>>,
+    /// True if the task has been started
+    started: AtomicBool,
+    /// Task object and optional health check, passed to the sanity callback
+    sanity:  UnsafeCell<TaskSanity>,
+}
+
+/// Argument for `task_sanity_callback()`: the task being checked and its optional health check
+struct TaskSanity {
+    /// Mynewt task object
+    task:   *mut os::os_task,
+    /// Optional health check, called by the sanity task
+    health: Option<fn() -> bool>,
+}
+
+/// `Task` is shared between tasks, but only the first call to `start()` may update the task object and entry.
+unsafe impl<T: Send + 'static, const N: usize> Sync for Task<T, N> {}
+
+impl<T: Send + 'static, const N: usize> Task<T, N> {
+    /// Return a task that has not been started. May be used in `static` declarations.
+    pub const fn new() -> Self {
+        Task {
+            task:    UnsafeCell::new(zeroed()),
+            stack:   UnsafeCell::new([0; N]),
+            entry:   UnsafeCell::new(None),
+            started: AtomicBool::new(false),
+            sanity:  UnsafeCell::new(TaskSanity { task: core::ptr::null_mut(), health: None }),
+        }
+    }
+
+    /// Create a Mynewt task named `name` that calls `func(arg)`, and start it.
+    /// `priority` is the task priority: highest is 0, lowest is 255 (main task is 127).
+    /// Returns `SYS_EALREADY` if the task has already been started, `SYS_EINVAL` if the stack is too large
+    /// or the name is not null-terminated.
+    /// If `func` returns, the task sleeps forever.
+    pub fn start(&'static self, name: &'static Strn, priority: u8, func: fn(T), arg: T) -> MynewtResult<TaskHandle> {
+        self.start_task(name, priority, func, arg, None)
+    }
+
+    /// Create a Mynewt task named `name` that calls `func(arg)`, and start it, like `start()`.
+    /// The task must call `sanity::checkin()` at least every `sanity.interval()`, unless the health check
+    /// returns true. Otherwise the task is considered hung: its name is shown on the console and the system resets.
+    pub fn start_with_sanity(&'static self, name: &'static Strn, priority: u8, func: fn(T), arg: T, sanity: SanityConfig) -> MynewtResult<TaskHandle> {
+        let ticks = sanity.interval().to_ticks();
+        if ticks == 0 || ticks == os::OS_TIMEOUT_NEVER { return Err(MynewtError::SYS_EINVAL); }
+        self.start_task(name, priority, func, arg, Some(sanity))
+    }
+
+    /// Create the Mynewt task and start it. If `sanity` is set, the task must check in every `sanity.interval()`,
+    /// which has been validated by the caller.
+    fn start_task(&'static self, name: &'static Strn, priority: u8, func: fn(T), arg: T, sanity: Option<SanityConfig>) -> MynewtResult<TaskHandle> {
+        if N > u16::max_value() as usize { return Err(MynewtError::SYS_EINVAL); }  //  Stack size must fit in `u16`
+        if !name.is_null_terminated() { return Err(MynewtError::SYS_EINVAL); }
+        if self.started.swap(true, Ordering::AcqRel) {
+            return Err(MynewtError::SYS_EALREADY);
+        }
+        //  Only this call may update the task object and entry, because `started` has been set.
+        unsafe { *self.entry.get() = Some((func, arg)) };
+        let sanity_itvl = match sanity {
+            Some(sanity) => {
+                unsafe { *self.sanity.get() = TaskSanity { task: self.task.get(), health: sanity.health() } };
+                sanity.interval().to_ticks()
+            }
+            None => os::OS_WAIT_FOREVER as u32,  //  Don't do sanity / watchdog checking
+        };
+        let res = os::task_init(       //  Create a new task and start it...
+            unsafe { &mut *self.task.get() },   //  Task object will be saved here
+            name,                        //  Name of task
+            Some(task_entry::<T, N>),    //  Function to execute when task starts
+            self as *const Self as Ptr,  //  Argument to be passed to above function
+            priority,                    //  Task priority
+            sanity_itvl,                 //  Sanity check interval in ticks
+            unsafe { &mut *self.stack.get() },  //  Stack space for the task
+            N as u16                     //  Size of the stack (in 4-byte units)
+        );
+        if res.is_ok() && sanity.is_some() {
+            //  Run the health check and show the task name if the task is hung. The sanity task
+            //  won't run the check until a full interval has passed, so the callback is set in time.
+            let check = unsafe { &mut (*self.task.get()).t_sanity_check };
+            check.sc_func = Some(task_sanity_callback);
+            check.sc_arg  = self.sanity.get() as Ptr;
+        }
+        if let Err(err) = res {  //  Task was not created, allow the task to be started again.
+            unsafe { *self.entry.get() = None };
+            unsafe { (*self.sanity.get()).health = None };
+            self.started.store(false, Ordering::Release);
+            return Err(err);
+        }
+        Ok(TaskHandle(self.task.get()))
+    }
+
+    /// Return the handle of the task if it has been started
+    pub fn handle(&'static self) -> Option<TaskHandle> {
+        if self.started.load(Ordering::Acquire) { Some(TaskHandle(self.task.get())) }
+        else { None }
+    }
+
+    /// Return the stack size in words (`os_stack_t`)
+    pub const fn stack_size(&self) -> usize {
+        N
+    }
+}
+
+/// Mynewt task function for `Task<T, N>`. Calls the task function with the task argument.
+extern "C" fn task_entry<T: Send + 'static, const N: usize>(arg: Ptr) {
+    let task = unsafe { &*(arg as *const Task<T, N>) };
+    if let Some((func, arg)) = unsafe { (*task.entry.get()).take() } {
+        func(arg);
+    }
+    //  Mynewt tasks must not return, so we sleep forever.
+    loop {
+        time::delay(Duration::FOREVER);
+    }
+}
+
+/// Mynewt sanity callback for tasks started with `start_with_sanity()`
+extern "C" fn task_sanity_callback(check: *mut os::os_sanity_check, arg: Ptr) -> ::cty::c_int {
+    let sanity = unsafe { &*(arg as *const TaskSanity) };
+    sanity::run_check(check, &TaskHandle(sanity.task).name(), sanity.health)
+}
+
+/// Handle to a Mynewt task
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TaskHandle(*mut os::os_task);
+
+unsafe impl Send for TaskHandle {}
+unsafe impl Sync for TaskHandle {}
+
+impl TaskHandle {
+    /// Return the handle of the currently running task
+    pub fn current() -> TaskHandle {
+        TaskHandle(unsafe { os::os_sched_get_current_task() })
+    }
+
+    /// Return the task name
+    pub fn name(&self) -> Strn<'static> {
+        Strn::from_cstr(unsafe { (*self.0).t_name } as *const u8)
+    }
+
+    /// Return the task ID
+    pub fn id(&self) -> u8 {
+        unsafe { (*self.0).t_taskid }
+    }
+
+    /// Return the task priority: highest is 0, lowest is 255
+    pub fn priority(&self) -> u8 {
+        unsafe { (*self.0).t_prio }
+    }
+
+    /// Return the Mynewt task object
+    pub fn as_ptr(&self) -> *mut os::os_task {
+        self.0
+    }
+}
+
+/// State of a Mynewt task
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskState {
+    /// Task is running or ready to run
+    Ready,
+    /// Task is waiting for a timeout, semaphore, mutex or event
+    Sleep,
+    /// Unknown state
+    Unknown(u8),
+}
+
+impl TaskState {
+    /// Return the task state for the Mynewt task state
+    fn from_os(state: u8) -> TaskState {
+        match state as os::os_task_state {
+            os::os_task_state_OS_TASK_READY => TaskState::Ready,
+            os::os_task_state_OS_TASK_SLEEP => TaskState::Sleep,
+            _ => TaskState::Unknown(state),
+        }
+    }
+
+    /// Return the short name of the state, e.g. `READY`
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskState::Ready      => "READY",
+            TaskState::Sleep      => "SLEEP",
+            TaskState::Unknown(_) => "?",
+        }
+    }
+}
+
+/// Snapshot of a Mynewt task, returned by `tasks()`
+#[derive(Clone, Copy, Debug)]
+pub struct TaskInfo {
+    /// Task that was inspected
+    handle:       TaskHandle,
+    /// Name of the task
+    name:         StrnBuf<TASK_NAME_LEN>,
+    /// Task ID
+    id:           u8,
+    /// Task priority
+    priority:     u8,
+    /// Task state
+    state:        TaskState,
+    /// Stack size in words
+    stack_size:   u16,
+    /// Highest stack usage in words
+    stack_used:   u16,
+    /// Total run time in OS ticks
+    run_time:     u32,
+    /// Number of context switches
+    ctx_switches: u32,
+    /// Last time the task checked in with the sanity task
+    last_checkin: os::os_time_t,
+    /// Next time the task must check in with the sanity task
+    next_checkin: os::os_time_t,
+}
+
+impl TaskInfo {
+    /// Return the handle of the task
+    pub fn handle(&self) -> TaskHandle { self.handle }
+
+    /// Return the task name
+    pub fn name(&self) -> Strn<'_> { self.name.as_strn() }
+
+    /// Return the task ID
+    pub fn id(&self) -> u8 { self.id }
+
+    /// Return the task priority: highest is 0, lowest is 255
+    pub fn priority(&self) -> u8 { self.priority }
+
+    /// Return the task state
+    pub fn state(&self) -> TaskState { self.state }
+
+    /// Return the stack size in bytes
+    pub fn stack_size(&self) -> usize {
+        self.stack_size as usize * core::mem::size_of::<os::os_stack_t>()
+    }
+
+    /// Return the highest stack usage in bytes, measured from the stack fill pattern
+    pub fn stack_used(&self) -> usize {
+        self.stack_used as usize * core::mem::size_of::<os::os_stack_t>()
+    }
+
+    /// Return the number of stack bytes that have never been used
+    pub fn stack_free(&self) -> usize {
+        self.stack_size().saturating_sub(self.stack_used())
+    }
+
+    /// Return the highest stack usage as a percentage of the stack size
+    pub fn stack_used_percent(&self) -> u8 {
+        if self.stack_size == 0 { return 0; }
+        (self.stack_used as u32 * 100 / self.stack_size as u32) as u8
+    }
+
+    /// Return the total time that the task has been running
+    pub fn run_time(&self) -> Duration { Duration::from_ticks(self.run_time) }
+
+    /// Return the number of times that the task has been switched in
+    pub fn context_switches(&self) -> u32 { self.ctx_switches }
+
+    /// Return the last time that the task checked in with the sanity task
+    pub fn last_checkin(&self) -> Instant { Instant::from_ticks(self.last_checkin) }
+
+    /// Return the next time that the task must check in with the sanity task
+    pub fn next_checkin(&self) -> Instant { Instant::from_ticks(self.next_checkin) }
+}
+
+/// Return an iterator over snapshots of all Mynewt tasks
+pub fn tasks() -> Tasks {
+    Tasks { prev: core::ptr::null_mut(), done: false }
+}
+
+/// Iterator over snapshots of all Mynewt tasks. Returned by `tasks()`.
+pub struct Tasks {
+    /// Task returned by the previous call to `os_task_info_get_next()`, or null to start from the first task
+    prev: *mut os::os_task,
+    /// True if all tasks have been returned
+    done: bool,
+}
+
+impl Iterator for Tasks {
+    type Item = TaskInfo;
+
+    fn next(&mut self) -> Option<TaskInfo> {
+        if self.done { return None; }
+        let mut oti: os::os_task_info = zeroed();
+        let task = unsafe { os::os_task_info_get_next(self.prev, &mut oti) };
+        if task.is_null() { self.done = true; return None; }
+        self.prev = task;
+        Some(TaskInfo {
+            handle:       TaskHandle(task),
+            name:         StrnBuf::from_c_array(&oti.oti_name),
+            id:           oti.oti_taskid,
+            priority:     oti.oti_prio,
+            state:        TaskState::from_os(oti.oti_state),
+            stack_size:   oti.oti_stksize,
+            stack_used:   oti.oti_stkusage,
+            run_time:     oti.oti_runtime,
+            ctx_switches: oti.oti_cswcnt,
+            last_checkin: oti.oti_last_checkin,
+            next_checkin: oti.oti_next_checkin,
+        })
+    }
+}
+
+/// `top`-like table of all Mynewt tasks, e.g. `write!(console::Console, "{}", TaskTable)`
+pub struct TaskTable;
+
+impl fmt::Display for TaskTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, " ID PRI STATE  STACK  USED   %      CSW   RUNTIME NAME")?;
+        for task in tasks() {
+            writeln!(f, "{:>3} {:>3} {:<5} {:>6} {:>5} {:>3} {:>8} {:>7}ms {}",
+                task.id(),
+                task.priority(),
+                task.state().name(),
+                task.stack_size(),
+                task.stack_used(),
+                task.stack_used_percent(),
+                task.context_switches(),
+                task.run_time().as_millis(),
+                task.name()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Display the `top`-like table of all Mynewt tasks on the console
+pub fn print_tasks() {
+    write!(console::Console, "{}", TaskTable).ok();
+    console::flush();
+}
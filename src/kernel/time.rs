@@ -0,0 +1,245 @@
+//! Time API for Mynewt. `Duration` is a span of time in milliseconds, converted to OS ticks for timeouts.
+//! `Instant` is a point in time measured in OS ticks, which wraps around when the tick counter overflows.
+//! Tick conversions use the tick rate configured for the BSP (`OS_TICKS_PER_SEC`).
+//! ```
+//! SPI_SEM.pend(Duration::from_secs(30)) ? ;
+//! let start = Instant::now();
+//! time::delay(Duration::from_millis(200));
+//! let elapsed = start.elapsed();
+//! ```
+
+use core::{
+    cmp::Ordering,
+    ops::{ Add, AddAssign, Sub, SubAssign },
+};
+use crate::kernel::os;
+
+/// Span of time in milliseconds. Used for timeouts in Mynewt kernel calls.
+/// `Duration::FOREVER` means wait forever.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Duration {
+    /// Number of milliseconds
+    millis: u32,
+}
+
+impl Duration {
+    /// Zero duration. Kernel calls return immediately instead of blocking.
+    pub const ZERO: Duration = Duration { millis: 0 };
+
+    /// Wait forever. Converted to `OS_TIMEOUT_NEVER` ticks.
+    pub const FOREVER: Duration = Duration { millis: u32::MAX };
+
+    /// Return a duration of `millis` milliseconds
+    pub const fn from_millis(millis: u32) -> Duration {
+        Duration { millis }
+    }
+
+    /// Return a duration of `secs` seconds. Saturates at `Duration::FOREVER`.
+    pub const fn from_secs(secs: u32) -> Duration {
+        Duration { millis: secs.saturating_mul(1000) }
+    }
+
+    /// Return the duration of `ticks` OS ticks, rounded down to milliseconds. Saturates at `Duration::FOREVER`.
+    pub fn from_ticks(ticks: os::os_time_t) -> Duration {
+        let mut millis: u32 = 0;
+        let rc = unsafe { os::os_time_ticks_to_ms(ticks, &mut millis) };
+        if rc != 0 { return Duration::FOREVER; }  //  Too large to fit in `u32`
+        Duration { millis }
+    }
+
+    /// Return the number of milliseconds
+    pub const fn as_millis(&self) -> u32 {
+        self.millis
+    }
+
+    /// Return the number of whole seconds
+    pub const fn as_secs(&self) -> u32 {
+        self.millis / 1000
+    }
+
+    /// Return true if this is `Duration::FOREVER`
+    pub const fn is_forever(&self) -> bool {
+        self.millis == u32::MAX
+    }
+
+    /// Return the number of OS ticks for the duration, rounded down.
+    /// `Duration::FOREVER` and durations too large for `os_time_t` become `OS_TIMEOUT_NEVER`.
+    pub fn to_ticks(&self) -> os::os_time_t {
+        if self.is_forever() { return os::OS_TIMEOUT_NEVER; }
+        let mut ticks: os::os_time_t = 0;
+        let rc = unsafe { os::os_time_ms_to_ticks(self.millis, &mut ticks) };
+        if rc != 0 { return os::OS_TIMEOUT_NEVER; }  //  Too large to fit in `os_time_t`
+        ticks
+    }
+
+    /// Return the number of OS ticks for the duration, rounded up so that waiting for the ticks
+    /// takes at least the duration. `Duration::FOREVER` becomes `OS_TIMEOUT_NEVER`.
+    pub fn to_ticks_ceil(&self) -> os::os_time_t {
+        let ticks = self.to_ticks();
+        if ticks == os::OS_TIMEOUT_NEVER { return ticks; }
+        if Duration::from_ticks(ticks).millis < self.millis { ticks + 1 }
+        else { ticks }
+    }
+
+    /// Add two durations. Returns `None` if the result overflows or is `Duration::FOREVER`.
+    pub fn checked_add(self, other: Duration) -> Option<Duration> {
+        match self.millis.checked_add(other.millis) {
+            Some(millis) if millis != u32::MAX => Some(Duration { millis }),
+            _ => None,
+        }
+    }
+
+    /// Subtract `other` from this duration. Returns `None` if the result is negative.
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        self.millis.checked_sub(other.millis).map(|millis| Duration { millis })
+    }
+
+    /// Add two durations. Saturates at `Duration::FOREVER`.
+    pub fn saturating_add(self, other: Duration) -> Duration {
+        Duration { millis: self.millis.saturating_add(other.millis) }
+    }
+
+    /// Subtract `other` from this duration. Saturates at `Duration::ZERO`.
+    pub fn saturating_sub(self, other: Duration) -> Duration {
+        Duration { millis: self.millis.saturating_sub(other.millis) }
+    }
+}
+
+/// Add two durations. Saturates at `Duration::FOREVER`.
+impl Add for Duration {
+    type Output = Duration;
+    fn add(self, other: Duration) -> Duration { self.saturating_add(other) }
+}
+
+/// Add a duration. Saturates at `Duration::FOREVER`.
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) { *self = *self + other; }
+}
+
+/// Subtract two durations. Saturates at `Duration::ZERO`.
+impl Sub for Duration {
+    type Output = Duration;
+    fn sub(self, other: Duration) -> Duration { self.saturating_sub(other) }
+}
+
+/// Subtract a duration. Saturates at `Duration::ZERO`.
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Duration) { *self = *self - other; }
+}
+
+/// Point in time, measured in OS ticks since the OS started. The tick counter wraps around, so instants
+/// may only be compared and subtracted if they are less than half the tick range apart
+/// (about 194 days at 128 ticks per second).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Instant {
+    /// OS tick count
+    ticks: os::os_time_t,
+}
+
+impl Instant {
+    /// Return the current time
+    pub fn now() -> Instant {
+        Instant { ticks: unsafe { os::os_time_get() } }
+    }
+
+    /// Return the instant for the OS tick count
+    pub const fn from_ticks(ticks: os::os_time_t) -> Instant {
+        Instant { ticks }
+    }
+
+    /// Return the OS tick count
+    pub const fn ticks(&self) -> os::os_time_t {
+        self.ticks
+    }
+
+    /// Return the number of ticks from `earlier` to this instant, or `None` if `earlier` is later than this instant.
+    /// Handles tick wraparound.
+    pub fn ticks_since(&self, earlier: Instant) -> Option<os::os_time_t> {
+        let diff = self.ticks.wrapping_sub(earlier.ticks);
+        if (diff as i32) < 0 { None }  //  Same as `OS_TIME_TICK_LT()`
+        else { Some(diff) }
+    }
+
+    /// Return the time from `earlier` to this instant. Returns `Duration::ZERO` if `earlier` is later than this instant.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        match self.ticks_since(earlier) {
+            Some(ticks) => Duration::from_ticks(ticks),
+            None        => Duration::ZERO,
+        }
+    }
+
+    /// Return the time elapsed since this instant
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Return the instant `duration` after this instant. Returns `None` for `Duration::FOREVER`
+    /// and durations too long to be compared with this instant.
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration.to_ticks_ceil();  //  Deadlines must not come before the full duration
+        if ticks > i32::max_value() as u32 { return None; }
+        Some(Instant { ticks: self.ticks.wrapping_add(ticks) })
+    }
+
+    /// Return the instant `duration` before this instant. Returns `None` for `Duration::FOREVER`
+    /// and durations too long to be compared with this instant.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration.to_ticks_ceil();
+        if ticks > i32::max_value() as u32 { return None; }
+        Some(Instant { ticks: self.ticks.wrapping_sub(ticks) })
+    }
+}
+
+/// Compare two instants, handling tick wraparound
+impl PartialOrd for Instant {
+    fn partial_cmp(&self, other: &Instant) -> Option<Ordering> {
+        Some((self.ticks.wrapping_sub(other.ticks) as i32).cmp(&0))
+    }
+}
+
+/// Return the instant `duration` after this instant. Fails for durations longer than half the tick range.
+impl Add<Duration> for Instant {
+    type Output = Instant;
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("instant overflow")
+    }
+}
+
+/// Return the instant `duration` before this instant. Fails for durations longer than half the tick range.
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("instant overflow")
+    }
+}
+
+/// Return the time between two instants. Returns `Duration::ZERO` if `earlier` is later.
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Sleep for at least `duration`. Other tasks will run while sleeping. `Duration::ZERO` returns immediately
+/// without yielding, `Duration::FOREVER` sleeps forever.
+pub fn delay(duration: Duration) {
+    unsafe { os::os_time_delay(duration.to_ticks_ceil()) };
+}
+
+/// Sleep until `deadline`. Returns immediately if `deadline` has passed.
+pub fn delay_until(deadline: Instant) {
+    if let Some(ticks) = deadline.ticks_since(Instant::now()) {
+        unsafe { os::os_time_delay(ticks) };
+    }
+}
+
+/// Return the number of microseconds since the OS started. Doesn't wrap around.
+pub fn uptime_micros() -> u64 {
+    unsafe { os::os_get_uptime_usec() as u64 }
+}
+
+/// Return the number of milliseconds since the OS started. Doesn't wrap around.
+pub fn uptime_millis() -> u64 {
+    uptime_micros() / 1000
+}
@@ -0,0 +1,214 @@
+//! Time-change listeners for Mynewt, built on `os_time_change_listen()` and `os_time_change_remove()`.
+//! The listener is called whenever the clock is set, e.g. when the phone syncs the time.
+//! Listeners may be declared as `static` and stay registered until the returned handle is dropped:
+//! ```
+//! /// Listener that reschedules alarms when the clock is set
+//! static CLOCK_LISTENER: TimeChangeListener<fn(&TimeChange)> = TimeChangeListener::new(on_clock_change);
+//!
+//! fn on_clock_change(change: &TimeChange) { ... }
+//!
+//! CLOCK_LISTENER.register_static() ?
+//!     .keep();  //  Stay registered forever
+//! ```
+//! Listeners on the stack may capture variables, and must be pinned before registering:
+//! ```
+//! let listener = core::pin::pin!(TimeChangeListener::new(|change: &TimeChange| { ... }));
+//! let _handle = listener.as_ref().register() ? ;  //  Unregistered when `_handle` is dropped
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomPinned,
+    pin::Pin,
+    sync::atomic::{ AtomicBool, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+        datetime::{ DateTime, TimeZone },
+    },
+    result::*,
+    util::zeroable::zeroed,
+    Ptr,
+};
+
+/// Clock change passed to time-change listeners: the UTC time and time zone before and after the change
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeChange {
+    /// Seconds since 1970-01-01 00:00:00 UTC, before the change
+    before_secs:   i64,
+    /// Microseconds after the second, before the change
+    before_micros: u32,
+    /// Time zone before the change
+    before_tz:     TimeZone,
+    /// Seconds since 1970-01-01 00:00:00 UTC, after the change
+    after_secs:    i64,
+    /// Microseconds after the second, after the change
+    after_micros:  u32,
+    /// Time zone after the change
+    after_tz:      TimeZone,
+    /// True if this is the first time the clock has been set
+    newly_synced:  bool,
+}
+
+impl TimeChange {
+    /// Translate the Mynewt time change info
+    fn from_os(info: &os::os_time_change_info) -> TimeChange {
+        let (before_secs, before_micros) = timeval(info.tci_prev_tv);
+        let (after_secs,  after_micros)  = timeval(info.tci_cur_tv);
+        TimeChange {
+            before_secs, before_micros,
+            before_tz: timezone(info.tci_prev_tz),
+            after_secs, after_micros,
+            after_tz:  timezone(info.tci_cur_tz),
+            newly_synced: info.tci_newly_synced,
+        }
+    }
+
+    /// Return the UNIX timestamp before the change
+    pub fn before_timestamp(&self) -> i64 { self.before_secs }
+
+    /// Return the UNIX timestamp after the change
+    pub fn after_timestamp(&self) -> i64 { self.after_secs }
+
+    /// Return the local date and time before the change
+    pub fn before(&self) -> MynewtResult<DateTime> {
+        DateTime::from_timestamp(self.before_secs, self.before_micros, self.before_tz)
+    }
+
+    /// Return the local date and time after the change
+    pub fn after(&self) -> MynewtResult<DateTime> {
+        DateTime::from_timestamp(self.after_secs, self.after_micros, self.after_tz)
+    }
+
+    /// Return the number of seconds that the UTC time jumped, negative if the clock was set backwards
+    pub fn delta_secs(&self) -> i64 {
+        self.after_secs - self.before_secs
+    }
+
+    /// Return true if the time zone or daylight saving time changed
+    pub fn timezone_changed(&self) -> bool {
+        self.before_tz != self.after_tz
+    }
+
+    /// Return true if this is the first time the clock has been set since boot
+    pub fn is_newly_synced(&self) -> bool {
+        self.newly_synced
+    }
+}
+
+/// Return the seconds and microseconds of the Mynewt time value, or 0 if null
+fn timeval(tv: *const os::os_timeval) -> (i64, u32) {
+    if tv.is_null() { return (0, 0); }
+    let tv = unsafe { &*tv };
+    (tv.tv_sec, tv.tv_usec as u32)
+}
+
+/// Return the time zone for the Mynewt time zone, or UTC if null
+fn timezone(tz: *const os::os_timezone) -> TimeZone {
+    if tz.is_null() { return TimeZone::UTC; }
+    let tz = unsafe { &*tz };
+    TimeZone::new(-tz.tz_minuteswest, false)
+        .unwrap_or(TimeZone::UTC)
+        .with_dst(tz.tz_dsttime != 0)
+}
+
+/// Listener that calls `callback` when the clock is set. Call `register()` or `register_static()` to start listening.
+/// The callback is called by the task that sets the clock, it must not register or unregister listeners.
+pub struct TimeChangeListener<F: Fn(&TimeChange) + Sync> {
+    /// Mynewt listener. Points to this `TimeChangeListener` when registered.
+    listener:   UnsafeCell<os::os_time_change_listener>,
+    /// True if the listener is registered with Mynewt
+    registered: AtomicBool,
+    /// Callback for the listener
+    callback:   F,
+    /// Mynewt keeps a pointer to the listener while registered, so it must not move
+    _pinned:    PhantomPinned,
+}
+
+/// Listener is registered and unregistered with interrupts locked out
+unsafe impl<F: Fn(&TimeChange) + Sync> Sync for TimeChangeListener<F> {}
+
+impl<F: Fn(&TimeChange) + Sync> TimeChangeListener<F> {
+    /// Return an unregistered listener that calls `callback`. May be used in `static` declarations.
+    pub const fn new(callback: F) -> Self {
+        TimeChangeListener {
+            listener: UnsafeCell::new(os::os_time_change_listener {
+                tcl_fn:   Some(listener_callback::<F>),
+                tcl_arg:  core::ptr::null_mut(),  //  Set when registered
+                tcl_next: zeroed(),
+            }),
+            registered: AtomicBool::new(false),
+            callback,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Start listening for clock changes. Returns a handle that unregisters the listener when dropped.
+    /// Returns `SYS_EALREADY` if the listener is already registered.
+    pub fn register(self: Pin<&Self>) -> MynewtResult<ListenerHandle<'_, F>> {
+        let listener = self.get_ref();
+        if listener.registered.swap(true, Ordering::AcqRel) {
+            return Err(MynewtError::SYS_EALREADY);
+        }
+        critical_section(|_cs| {
+            unsafe { (*listener.listener.get()).tcl_arg = listener as *const Self as Ptr };
+            unsafe { os::os_time_change_listen(listener.listener.get()) };
+        });
+        Ok(ListenerHandle { listener })
+    }
+
+    /// Start listening for clock changes with a `static` listener. Returns a handle that unregisters
+    /// the listener when dropped, call `keep()` on the handle to stay registered.
+    pub fn register_static(&'static self) -> MynewtResult<ListenerHandle<'static, F>> {
+        Pin::static_ref(self).register()
+    }
+
+    /// Return true if the listener is registered
+    pub fn is_registered(&self) -> bool {
+        self.registered.load(Ordering::Acquire)
+    }
+
+    /// Stop listening for clock changes
+    fn unregister(&self) {
+        if !self.registered.swap(false, Ordering::AcqRel) { return; }
+        let rc = critical_section(|_cs| unsafe { os::os_time_change_remove(self.listener.get()) });
+        assert_eq!(rc, 0, "listener fail");
+    }
+}
+
+/// If the listener is still registered, unregister it before the memory is reused
+impl<F: Fn(&TimeChange) + Sync> Drop for TimeChangeListener<F> {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+/// Mynewt callback for `TimeChangeListener<F>`. Calls the Rust callback with the translated time change.
+extern "C" fn listener_callback<F: Fn(&TimeChange) + Sync>(info: *const os::os_time_change_info, arg: Ptr) {
+    if info.is_null() || arg.is_null() { return; }
+    let listener = unsafe { &*(arg as *const TimeChangeListener<F>) };
+    let change = TimeChange::from_os(unsafe { &*info });
+    (listener.callback)(&change);
+}
+
+/// Registration of a `TimeChangeListener`. Unregisters the listener when dropped.
+pub struct ListenerHandle<'a, F: Fn(&TimeChange) + Sync> {
+    /// Registered listener
+    listener: &'a TimeChangeListener<F>,
+}
+
+impl<F: Fn(&TimeChange) + Sync> ListenerHandle<'static, F> {
+    /// Keep the `static` listener registered forever
+    pub fn keep(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl<'a, F: Fn(&TimeChange) + Sync> Drop for ListenerHandle<'a, F> {
+    /// Unregister the listener
+    fn drop(&mut self) {
+        self.listener.unregister();
+    }
+}
@@ -0,0 +1,151 @@
+//! Software timers for Mynewt, built on `os_callout`. When a timer expires, its Rust handler is called by the task
+//! that processes the timer's event queue. Timers may be declared as `static` without `unsafe`:
+//! ```
+//! /// Timer that redraws the watch face every second
+//! static REDRAW_TIMER: Timer = Timer::new(redraw);
+//!
+//! fn redraw(_timer: &'static Timer) { ... }
+//!
+//! REDRAW_TIMER.bind(&DISPLAY_QUEUE);  //  Optional: Call the handler in the display task instead of the main task
+//! REDRAW_TIMER.start_periodic(Duration::from_secs(1)) ? ;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{ AtomicU32, Ordering },
+};
+use crate::{
+    kernel::{
+        os,
+        critical::critical_section,
+        event::EventQueue,
+        time::Duration,
+    },
+    result::*,
+    util::zeroable::zeroed,
+};
+
+/// Software timer that calls a Rust handler when it expires. May be one-shot (`start_once()`) or
+/// periodic (`start_periodic()`). The handler may restart or stop the timer.
+#[repr(C)]
+pub struct Timer {
+    /// Mynewt callout. Must be the first field, because the timer callback converts the `os_event` pointer
+    /// (first field of `os_callout`) to `Timer`.
+    callout: UnsafeCell<os::os_callout>,
+    /// Rust handler for the timer
+    handler: fn(&'static Timer),
+    /// Period in OS ticks for periodic timers, 0 for one-shot timers
+    period:  AtomicU32,
+}
+
+/// Mynewt callouts may be started and stopped by multiple tasks
+unsafe impl Sync for Timer {}
+
+impl Timer {
+    /// Return a stopped timer that calls `handler` when it expires. May be used in `static` declarations.
+    /// The handler is called by the task that processes the default event queue, unless `bind()` is called.
+    pub const fn new(handler: fn(&'static Timer)) -> Self {
+        Timer {
+            callout: UnsafeCell::new(os::os_callout {
+                c_ev: os::os_event {
+                    ev_queued: 0,
+                    ev_cb:     Some(timer_callback),
+                    ev_arg:    core::ptr::null_mut(),
+                    ev_next:   zeroed(),
+                },
+                c_evq:   core::ptr::null_mut(),  //  Bound to the default event queue when started
+                c_ticks: 0,
+                c_next:  zeroed(),
+            }),
+            handler,
+            period: AtomicU32::new(0),
+        }
+    }
+
+    /// Call the handler in the task that processes `queue`. Stops the timer if it's running.
+    pub fn bind(&'static self, queue: &'static EventQueue) {
+        self.stop();
+        self.set_queue(queue);
+    }
+
+    /// Start the timer to expire once after `delay`. Restarts the timer if it's running.
+    /// May be called by the timer handler.
+    pub fn start_once(&'static self, delay: Duration) -> MynewtResult<()> {
+        self.period.store(0, Ordering::Release);
+        self.reset(delay.to_ticks_ceil())
+    }
+
+    /// Start the timer to expire every `period`. Restarts the timer if it's running.
+    /// May be called by the timer handler.
+    pub fn start_periodic(&'static self, period: Duration) -> MynewtResult<()> {
+        let ticks = period.to_ticks_ceil();
+        if ticks == 0 || ticks == os::OS_TIMEOUT_NEVER { return Err(MynewtError::SYS_EINVAL); }
+        self.period.store(ticks, Ordering::Release);
+        self.reset(ticks)
+    }
+
+    /// Stop the timer. Pending handler calls are cancelled. May be called by the timer handler.
+    pub fn stop(&'static self) {
+        self.period.store(0, Ordering::Release);
+        unsafe { os::os_callout_stop(self.callout.get()) };
+    }
+
+    /// Return true if the timer is running
+    pub fn is_active(&self) -> bool {
+        //  Same check as `os_callout_queued()`
+        unsafe { !(*self.callout.get()).c_next.tqe_prev.is_null() }
+    }
+
+    /// Return true if the timer was started with `start_periodic()` and has not been stopped
+    pub fn is_periodic(&self) -> bool {
+        self.period.load(Ordering::Acquire) != 0
+    }
+
+    /// Return the time remaining until the timer expires, or `Duration::ZERO` if the timer is stopped
+    pub fn remaining(&self) -> Duration {
+        if !self.is_active() { return Duration::ZERO; }
+        let ticks = unsafe { os::os_callout_remaining_ticks(self.callout.get(), os::os_time_get()) };
+        Duration::from_ticks(ticks)
+    }
+
+    /// Return the Mynewt callout
+    pub fn as_ptr(&self) -> *mut os::os_callout {
+        self.callout.get()
+    }
+
+    /// Start the callout to expire after `ticks`. Bind to the default event queue if not bound.
+    fn reset(&'static self, ticks: os::os_time_t) -> MynewtResult<()> {
+        if unsafe { (*self.callout.get()).c_evq.is_null() } {
+            self.set_queue(EventQueue::default_queue());  //  Timer is not running because it's not bound
+        }
+        let rc = unsafe { os::os_callout_reset(self.callout.get(), ticks) };
+        if rc != 0 { return Err(MynewtError::from_os(rc as os::os_error_t)); }
+        Ok(())
+    }
+
+    /// Post the timer event to `queue` when the timer expires
+    fn set_queue(&self, queue: &'static EventQueue) {
+        let evq = queue.as_ptr();
+        critical_section(|_cs| unsafe { (*self.callout.get()).c_evq = evq });
+    }
+}
+
+/// Mynewt callback for `Timer`. Restarts periodic timers, then calls the Rust handler.
+extern "C" fn timer_callback(event: *mut os::os_event) {
+    let timer: &'static Timer = unsafe { &*(event as *const Timer) };
+    //  Lock out interrupts and other tasks, so that the timer is not restarted after `stop()`.
+    let rc = critical_section(|_cs| {
+        let period = timer.period.load(Ordering::Acquire);
+        if period != 0 {
+            //  Schedule the next expiry relative to this expiry, so the timer doesn't drift.
+            //  If we are late by more than a period, expire immediately.
+            let expiry = unsafe { (*timer.callout.get()).c_ticks };
+            let delay = expiry.wrapping_add(period).wrapping_sub(unsafe { os::os_time_get() });
+            let delay = if (delay as i32) < 0 { 0 } else { delay };
+            unsafe { os::os_callout_reset(timer.callout.get(), delay) }
+        } else { 0 }
+    });
+    assert_eq!(rc, 0, "timer fail");
+    //  Handler may restart or stop the timer, which overrides the restart above.
+    (timer.handler)(timer);
+}
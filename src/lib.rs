@@ -42,80 +42,7 @@ pub fn sysinit() {
     sys::console::flush();
 }
 
-/// Return type and error codes for Mynewt API
-pub mod result {
-    use crate::kernel::os;
-
-    /// Common return type for Mynewt API.  If no error, returns `Ok(val)` where val has type T.
-    /// Upon error, returns `Err(err)` where err is the MynewtError error code.
-    pub type MynewtResult<T> = ::core::result::Result<T, MynewtError>;
-
-    /// Error codes for Mynewt API
-    #[repr(i32)]
-    #[derive(PartialEq)]
-    #[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
-    pub enum MynewtError {
-        /// Error code 0 means no error.
-        SYS_EOK         = os::SYS_EOK as i32,
-        SYS_ENOMEM      = os::SYS_ENOMEM,
-        SYS_EINVAL      = os::SYS_EINVAL,
-        SYS_ETIMEOUT    = os::SYS_ETIMEOUT,
-        SYS_ENOENT      = os::SYS_ENOENT,
-        SYS_EIO         = os::SYS_EIO,
-        SYS_EAGAIN      = os::SYS_EAGAIN,
-        SYS_EACCES      = os::SYS_EACCES,
-        SYS_EBUSY       = os::SYS_EBUSY,
-        SYS_ENODEV      = os::SYS_ENODEV,
-        SYS_ERANGE      = os::SYS_ERANGE,
-        SYS_EALREADY    = os::SYS_EALREADY,
-        SYS_ENOTSUP     = os::SYS_ENOTSUP,
-        SYS_EUNKNOWN    = os::SYS_EUNKNOWN,
-        SYS_EREMOTEIO   = os::SYS_EREMOTEIO,
-        SYS_EDONE       = os::SYS_EDONE,
-        SYS_EPERUSER    = os::SYS_EPERUSER,
-        HAL_I2C_ERR_UNKNOWN,
-        HAL_I2C_ERR_INVAL,
-        HAL_I2C_ERR_TIMEOUT,
-        HAL_I2C_ERR_ADDR_NACK,
-        HAL_I2C_ERR_DATA_NACK,
-    }
-
-    /// Cast `MynewtError` to `i32`
-    impl From<MynewtError> for i32 {
-        /// Cast `MynewtError` to `i32`
-        fn from(err: MynewtError) -> Self {
-            err as i32
-        }
-    }
-
-    /// Cast `i32` to `MynewtError`
-    impl From<i32> for MynewtError {
-        /// Cast `i32` to `MynewtError`
-        fn from(num: i32) -> Self {
-            unsafe { 
-                ::core::mem::transmute::
-                    <i32, MynewtError>
-                    (num)
-            }  
-        }
-    }
-
-    /// Cast `()` to `MynewtError`
-    impl From<()> for MynewtError {
-        /// Cast `()` to `MynewtError`
-        fn from(_: ()) -> Self {
-            MynewtError::SYS_EUNKNOWN
-        }
-    }
-
-    /// Implement formatted output for MynewtError
-    impl core::fmt::Debug for MynewtError {
-        fn fmt(&self, _fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
-            //  TODO
-            Ok(())
-        }
-    }
-}
+pub mod result;  //  Export `result.rs` as Rust module `mynewt::result`
 
 /// Represents a null-terminated string, suitable for passing to Mynewt APIs as `* const char`.
 /// The string could be a null-terminated byte string created in Rust, or a pointer to a null-terminated string returned by C.
@@ -164,22 +91,45 @@ impl<'a> Strn<'a> {
         }
     }
 
-    /// Return the length of the string, excluding the terminating null. For safety, we limit to 128.
+    /// Return the length of the string, excluding the terminating null.
+    /// A byte string without a terminating null is counted up to its end. For a string pointer returned by C,
+    /// the string is truncated at `Strn::max_len()` bytes if the terminating null is not found.
     pub fn len(&self) -> usize {
         match self.rep {
             StrnRep::ByteStr(bs) => { 
-                assert_eq!(bs.last(), Some(&0u8), "no null");  //  Last byte must be 0.
-                bs.len() - 1  //  Don't count the terminating null.
+                bs.iter().position(|b| *b == 0).unwrap_or(bs.len())  //  Don't count the terminating null.
+            }
+            StrnRep::CStr(_cstr) => { 
+                let max = Strn::max_len();
+                self.len_max(max.saturating_add(1)).unwrap_or(max)
+            }
+        }
+    }
+
+    /// Return the max length of strings returned by C, excluding the terminating null. Defaults to `STRN_MAX_LEN`.
+    pub fn max_len() -> usize {
+        STRN_MAX_LEN_LIMIT.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Set the max length of strings returned by C, excluding the terminating null. Longer strings are truncated
+    /// by `len()`, `as_bytes()`, comparison and display.
+    pub fn set_max_len(max: usize) {
+        STRN_MAX_LEN_LIMIT.store(max, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Return the length of the string, excluding the terminating null.
+    /// Return `None` if the terminating null is not found within the first `max` bytes.
+    /// Used to check strings returned by C that may be corrupted.
+    pub fn len_max(&self, max: usize) -> Option<usize> {
+        match self.rep {
+            StrnRep::ByteStr(bs) => { 
+                let len = bs.iter().position(|b| *b == 0) ? ;  //  Look for the null termination.
+                if len < max { Some(len) } else { None }
             }
             StrnRep::CStr(cstr)  => { 
                 //  Look for the null termination.
-                if cstr.is_null() { return 0; }
-                for len in 0..127 {
-                    let ptr: *const u8 =  ((cstr as u32) + len) as *const u8;
-                    if unsafe { *ptr } == 0 { return len as usize; }                    
-                }
-                assert!(false, "big strn");  //  String too long
-                return 128 as usize;
+                if cstr.is_null() { return Some(0); }
+                (0..max).find(|len| unsafe { *cstr.add(*len) } == 0)
             }
         }
     }
@@ -201,21 +151,58 @@ impl<'a> Strn<'a> {
         }
     }
 
-    /// Return the byte string.
-    /// Fail if the last byte is not zero.
+    /// Return the byte string, including the terminating null.
+    /// If the string has no terminating null within its length, the bytes are returned without null.
     pub fn as_bytestr(&self) -> &'a [u8] {
         match self.rep {
-            StrnRep::ByteStr(bs) => {                
-                assert_eq!(bs.last(), Some(&0u8), "no null");  //  Last byte must be 0.
-                &bs
-            }
-            StrnRep::CStr(_cstr)  => { 
-                assert!(false, "strn cstr");  //  Not implemented
-                b"\0"
+            StrnRep::ByteStr(bs) => { bs }
+            StrnRep::CStr(cstr)  => { 
+                if cstr.is_null() { return b"\0"; }
+                let max = Strn::max_len();
+                //  Include the terminating null, if found.
+                let len = self.len_max(max.saturating_add(1)).map(|len| len + 1).unwrap_or(max);
+                unsafe { core::slice::from_raw_parts(cstr, len) }
             }
         }
     }
 
+    /// Return the bytes of the string, excluding the terminating null.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.as_bytestr()[..self.len()]  //  Don't include the terminating null.
+    }
+
+    /// Return the bytes of the string, including the terminating null.
+    pub fn as_bytes_with_nul(&self) -> &'a [u8] {
+        self.as_bytestr()
+    }
+
+    /// Return the string as `&str` if it contains valid UTF-8, excluding the terminating null.
+    pub fn to_str(&self) -> Result<&'a str, core::str::Utf8Error> {
+        core::str::from_utf8(self.as_bytes())
+    }
+
+    /// Return true if the string contains valid UTF-8
+    pub fn is_utf8(&self) -> bool {
+        self.to_str().is_ok()
+    }
+
+    /// Return the length of the leading valid UTF-8 part of the string, in bytes.
+    /// Returns `len()` if the entire string is valid UTF-8.
+    pub fn valid_utf8_len(&self) -> usize {
+        match self.to_str() {
+            Ok(s)    => s.len(),
+            Err(err) => err.valid_up_to(),
+        }
+    }
+
+    /// Return true if the last byte is zero. Strings returned by C are assumed to be null-terminated.
+    pub fn is_null_terminated(&self) -> bool {
+        match self.rep {
+            StrnRep::ByteStr(bs) => { bs.last() == Some(&0u8) }
+            StrnRep::CStr(_cstr) => { true }
+        }
+    }
+
     /// Fail if the last byte is not zero.
     pub fn validate(&self) {
         match self.rep {
@@ -232,12 +219,230 @@ impl<'a> Strn<'a> {
     }
 }
 
+/// Default max length of strings returned by C, excluding the terminating null. See `Strn::set_max_len()`.
+pub const STRN_MAX_LEN: usize = 256;
+
+/// Max length of strings returned by C, excluding the terminating null
+static STRN_MAX_LEN_LIMIT: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(STRN_MAX_LEN);
+
+/// Compare the bytes of two strings, regardless of representation
+impl<'a, 'b> PartialEq<Strn<'b>> for Strn<'a> {
+    fn eq(&self, other: &Strn<'b>) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a> Eq for Strn<'a> {}
+
+/// Compare the bytes of the string with a Rust string
+impl<'a> PartialEq<str> for Strn<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+/// Compare the bytes of the string with a Rust string
+impl<'a, 'b> PartialEq<&'b str> for Strn<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+/// Display the string. Invalid UTF-8 sequences are displayed as `U+FFFD`.
+impl<'a> core::fmt::Display for Strn<'a> {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut bytes = self.as_bytes();
+        loop {
+            match core::str::from_utf8(bytes) {
+                Ok(s) => { return fmt.write_str(s); }
+                Err(err) => {
+                    //  Display the valid part, then skip the invalid sequence.
+                    let (valid, rest) = bytes.split_at(err.valid_up_to());
+                    fmt.write_str(unsafe { core::str::from_utf8_unchecked(valid) }) ? ;
+                    fmt.write_str("\u{FFFD}") ? ;
+                    let skip = err.error_len().unwrap_or(rest.len());
+                    bytes = &rest[skip..];
+                }
+            }
+        }
+    }
+}
+
+/// Display the string in quotes
+impl<'a> core::fmt::Debug for Strn<'a> {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(fmt, "\"{}\"", self)
+    }
+}
+
 ///  Allow threads to share Strn, since it is static.
 unsafe impl<'a> Send for Strn<'a> {}
 
 ///  Allow threads to share Strn, since it is static.
 unsafe impl<'a> Sync for Strn<'a> {}
 
+/// Fixed-capacity, null-terminated string buffer that may be allocated on the stack or as a `static`.
+/// Holds up to `N - 1` bytes, followed by the terminating null. Used for composing strings at runtime
+/// that will be passed to Mynewt APIs as `Strn`, e.g. device names.
+/// ```
+/// let mut devname = StrnBuf::<16>::new();
+/// write!(devname, "bma421_{}", 0) ? ;
+/// let sensor = sensor_mgr::find_bydevname(&devname.as_strn()).next();
+/// ```
+#[derive(Clone, Copy)]  //  StrnBuf may be copied
+pub struct StrnBuf<const N: usize> {
+    /// Bytes of the string, followed by null. Bytes after the terminating null are always 0.
+    buf: [u8; N],
+    /// Length of the string, excluding the terminating null
+    len: usize,
+}
+
+impl<const N: usize> StrnBuf<N> {
+    /// Create a new empty string buffer
+    pub const fn new() -> Self {
+        StrnBuf {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Create a new string buffer from a null-terminated C array, e.g. a name field in a Mynewt info struct.
+    /// The string is truncated at the first null, or when the buffer is full.
+    pub fn from_c_array(array: &[::cty::c_char]) -> Self {
+        let mut buf = Self::new();
+        let len = array.iter().position(|&c| c == 0).unwrap_or(array.len())
+            .min(buf.capacity());
+        for (dst, &src) in buf.buf.iter_mut().zip(&array[..len]) {
+            *dst = src as u8;
+        }
+        buf.len = len;
+        buf
+    }
+
+    /// Return the max length of the string, excluding the terminating null
+    pub const fn capacity(&self) -> usize {
+        if N == 0 { 0 } else { N - 1 }
+    }
+
+    /// Return the length of the string, excluding the terminating null
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the string is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Erase the string
+    pub fn clear(&mut self) {
+        self.buf[..self.len].iter_mut().for_each(|b| *b = 0);
+        self.len = 0;
+    }
+
+    /// Append a byte. Fail with `SYS_EINVAL` if the byte is null, or `SYS_ENOMEM` if the buffer is full.
+    pub fn push(&mut self, b: u8) -> result::MynewtResult<()> {
+        self.push_bytes(&[b])
+    }
+
+    /// Append a Rust string. Fail with `SYS_EINVAL` if the string contains null,
+    /// or `SYS_ENOMEM` if the buffer is full. Upon failure the string buffer is not changed.
+    pub fn push_str(&mut self, s: &str) -> result::MynewtResult<()> {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Append bytes. Fail with `SYS_EINVAL` if the bytes contain null,
+    /// or `SYS_ENOMEM` if the buffer is full. Upon failure the string buffer is not changed.
+    pub fn push_bytes(&mut self, bs: &[u8]) -> result::MynewtResult<()> {
+        if bs.contains(&0) { return Err(result::MynewtError::SYS_EINVAL); }  //  Null would truncate the C string
+        if self.len + bs.len() > self.capacity() { return Err(result::MynewtError::SYS_ENOMEM); }
+        self.buf[self.len..self.len + bs.len()].copy_from_slice(bs);
+        self.len += bs.len();  //  Terminating null is already there, because unused bytes are 0.
+        Ok(())
+    }
+
+    /// Append formatted text. Called by `write!(buf, ...)`, returning `SYS_ENOMEM` if the buffer is full.
+    /// Upon failure the text written before the overflow is kept.
+    pub fn write_fmt(&mut self, args: core::fmt::Arguments) -> result::MynewtResult<()> {
+        core::fmt::Write::write_fmt(self, args)
+            .map_err(|_| result::MynewtError::SYS_ENOMEM)
+    }
+
+    /// Return the bytes of the string, excluding the terminating null
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Return the string as `&str` if it contains valid UTF-8
+    pub fn to_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(self.as_bytes())
+    }
+
+    /// Return a `Strn` that borrows the string buffer, suitable for passing to Mynewt APIs
+    pub fn as_strn(&self) -> Strn<'_> {
+        if N == 0 { return Strn::new(b"\0"); }  //  No space for the terminating null
+        Strn::new(&self.buf[..self.len + 1])  //  Include the terminating null
+    }
+
+    /// Return a `* const char` pointer to the null-terminated string
+    pub fn as_ptr(&self) -> *const u8 {
+        self.as_strn().as_ptr()
+    }
+}
+
+/// Default string buffer is empty
+impl<const N: usize> Default for StrnBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Create a new string buffer containing `s`, e.g. `"bma421_0".parse::<StrnBuf<16>>()`.
+/// Fail with `SYS_ENOMEM` if `s` is too long.
+impl<const N: usize> core::str::FromStr for StrnBuf<N> {
+    type Err = result::MynewtError;
+    fn from_str(s: &str) -> result::MynewtResult<Self> {
+        let mut buf = Self::new();
+        buf.push_str(s) ? ;
+        Ok(buf)
+    }
+}
+
+/// Allow `StrnBuf` to be used as a formatting destination. Returns `fmt::Error` if the buffer is full.
+impl<const N: usize> core::fmt::Write for StrnBuf<N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_str(s).map_err(|_| core::fmt::Error)
+    }
+}
+
+/// Display the string
+impl<const N: usize> core::fmt::Display for StrnBuf<N> {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.as_strn(), fmt)
+    }
+}
+
+/// Display the string in quotes
+impl<const N: usize> core::fmt::Debug for StrnBuf<N> {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.as_strn(), fmt)
+    }
+}
+
+/// Compare the bytes of the string buffer with a Rust string
+impl<const N: usize> PartialEq<str> for StrnBuf<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+/// Compare the bytes of the string buffer with a Rust string
+impl<'b, const N: usize> PartialEq<&'b str> for StrnBuf<N> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
 ///  Declare a pointer that will be used by C functions to return a value
 pub type Out<T> = &'static mut T;
 
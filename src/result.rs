@@ -0,0 +1,612 @@
+//! Return type and error codes for Mynewt API
+
+use crate::{
+    encoding::tinycbor,
+    hw::hal,
+    kernel::os,
+};
+
+pub mod context;  //  Export `result/context.rs` as Rust module `mynewt::result::context`
+pub use self::context::ResultContext;  //  Allow `.context()` to be called on `MynewtResult`
+
+/// Common return type for Mynewt API.  If no error, returns `Ok(val)` where val has type T.
+/// Upon error, returns `Err(err)` where err is the MynewtError error code.
+pub type MynewtResult<T> = ::core::result::Result<T, MynewtError>;
+
+/// Error codes for Mynewt API. Error codes that are not recognised are kept in `Other`,
+/// so that converting an error code to `MynewtError` and back never loses the code.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
+pub enum MynewtError {
+    /// Error code 0 means no error.
+    SYS_EOK,
+    SYS_ENOMEM,
+    SYS_EINVAL,
+    SYS_ETIMEOUT,
+    SYS_ENOENT,
+    SYS_EIO,
+    SYS_EAGAIN,
+    SYS_EACCES,
+    SYS_EBUSY,
+    SYS_ENODEV,
+    SYS_ERANGE,
+    SYS_EALREADY,
+    SYS_ENOTSUP,
+    SYS_EUNKNOWN,
+    SYS_EREMOTEIO,
+    SYS_EDONE,
+    SYS_EPERUSER,
+    HAL_I2C_ERR_UNKNOWN,
+    HAL_I2C_ERR_INVAL,
+    HAL_I2C_ERR_TIMEOUT,
+    HAL_I2C_ERR_ADDR_NACK,
+    HAL_I2C_ERR_DATA_NACK,
+    /// `os_error_t` codes returned by the Mynewt kernel, e.g. `os_sem_pend()`
+    OS_ENOMEM,
+    OS_EINVAL,
+    OS_INVALID_PARM,
+    OS_MEM_NOT_ALIGNED,
+    OS_BAD_MUTEX,
+    OS_TIMEOUT,
+    OS_ERR_IN_ISR,
+    OS_ERR_PRIV,
+    OS_NOT_STARTED,
+    OS_ENOENT,
+    OS_EBUSY,
+    OS_ERROR,
+    /// TinyCBOR encoding or decoding failure
+    Cbor(CborError),
+    /// CoAP payload encoding failure
+    Coap(CoapError),
+    /// SPI or GPIO HAL failure
+    Hal(HalError),
+    /// Sensor or Sensor Listener failure
+    Sensor(SensorError),
+    /// Error code that doesn't match any of the above, e.g. application-defined errors
+    Other(i32),
+}
+
+impl MynewtError {
+    /// Return the numeric error code, as returned by the Mynewt API.
+    /// CBOR, CoAP, HAL and sensor errors are returned as the nearest `SYS_*` code,
+    /// so that the code may be passed back to Mynewt.
+    pub fn code(&self) -> i32 {
+        use MynewtError::*;
+        match *self {
+            SYS_EOK               => os::SYS_EOK as i32,
+            SYS_ENOMEM            => os::SYS_ENOMEM,
+            SYS_EINVAL            => os::SYS_EINVAL,
+            SYS_ETIMEOUT          => os::SYS_ETIMEOUT,
+            SYS_ENOENT            => os::SYS_ENOENT,
+            SYS_EIO               => os::SYS_EIO,
+            SYS_EAGAIN            => os::SYS_EAGAIN,
+            SYS_EACCES            => os::SYS_EACCES,
+            SYS_EBUSY             => os::SYS_EBUSY,
+            SYS_ENODEV            => os::SYS_ENODEV,
+            SYS_ERANGE            => os::SYS_ERANGE,
+            SYS_EALREADY          => os::SYS_EALREADY,
+            SYS_ENOTSUP           => os::SYS_ENOTSUP,
+            SYS_EUNKNOWN          => os::SYS_EUNKNOWN,
+            SYS_EREMOTEIO         => os::SYS_EREMOTEIO,
+            SYS_EDONE             => os::SYS_EDONE,
+            SYS_EPERUSER          => os::SYS_EPERUSER,
+            HAL_I2C_ERR_UNKNOWN   => hal::HAL_I2C_ERR_UNKNOWN as i32,
+            HAL_I2C_ERR_INVAL     => hal::HAL_I2C_ERR_INVAL as i32,
+            HAL_I2C_ERR_TIMEOUT   => hal::HAL_I2C_ERR_TIMEOUT as i32,
+            HAL_I2C_ERR_ADDR_NACK => hal::HAL_I2C_ERR_ADDR_NACK as i32,
+            HAL_I2C_ERR_DATA_NACK => hal::HAL_I2C_ERR_DATA_NACK as i32,
+            OS_ENOMEM             => os::os_error_OS_ENOMEM as i32,
+            OS_EINVAL             => os::os_error_OS_EINVAL as i32,
+            OS_INVALID_PARM       => os::os_error_OS_INVALID_PARM as i32,
+            OS_MEM_NOT_ALIGNED    => os::os_error_OS_MEM_NOT_ALIGNED as i32,
+            OS_BAD_MUTEX          => os::os_error_OS_BAD_MUTEX as i32,
+            OS_TIMEOUT            => os::os_error_OS_TIMEOUT as i32,
+            OS_ERR_IN_ISR         => os::os_error_OS_ERR_IN_ISR as i32,
+            OS_ERR_PRIV           => os::os_error_OS_ERR_PRIV as i32,
+            OS_NOT_STARTED        => os::os_error_OS_NOT_STARTED as i32,
+            OS_ENOENT             => os::os_error_OS_ENOENT as i32,
+            OS_EBUSY              => os::os_error_OS_EBUSY as i32,
+            OS_ERROR              => os::os_error_OS_ERROR as i32,
+            Cbor(err)             => err.sys_code(),
+            Coap(_)               => os::SYS_EINVAL,
+            Hal(_)                => os::SYS_EIO,
+            Sensor(err)           => err.sys_code(),
+            Other(code)           => code,
+        }
+    }
+
+    /// Return the symbolic name of the error, e.g. `SYS_ETIMEOUT`
+    pub fn name(&self) -> &'static str {
+        use MynewtError::*;
+        match *self {
+            SYS_EOK               => "SYS_EOK",
+            SYS_ENOMEM            => "SYS_ENOMEM",
+            SYS_EINVAL            => "SYS_EINVAL",
+            SYS_ETIMEOUT          => "SYS_ETIMEOUT",
+            SYS_ENOENT            => "SYS_ENOENT",
+            SYS_EIO               => "SYS_EIO",
+            SYS_EAGAIN            => "SYS_EAGAIN",
+            SYS_EACCES            => "SYS_EACCES",
+            SYS_EBUSY             => "SYS_EBUSY",
+            SYS_ENODEV            => "SYS_ENODEV",
+            SYS_ERANGE            => "SYS_ERANGE",
+            SYS_EALREADY          => "SYS_EALREADY",
+            SYS_ENOTSUP           => "SYS_ENOTSUP",
+            SYS_EUNKNOWN          => "SYS_EUNKNOWN",
+            SYS_EREMOTEIO         => "SYS_EREMOTEIO",
+            SYS_EDONE             => "SYS_EDONE",
+            SYS_EPERUSER          => "SYS_EPERUSER",
+            HAL_I2C_ERR_UNKNOWN   => "HAL_I2C_ERR_UNKNOWN",
+            HAL_I2C_ERR_INVAL     => "HAL_I2C_ERR_INVAL",
+            HAL_I2C_ERR_TIMEOUT   => "HAL_I2C_ERR_TIMEOUT",
+            HAL_I2C_ERR_ADDR_NACK => "HAL_I2C_ERR_ADDR_NACK",
+            HAL_I2C_ERR_DATA_NACK => "HAL_I2C_ERR_DATA_NACK",
+            OS_ENOMEM             => "OS_ENOMEM",
+            OS_EINVAL             => "OS_EINVAL",
+            OS_INVALID_PARM       => "OS_INVALID_PARM",
+            OS_MEM_NOT_ALIGNED    => "OS_MEM_NOT_ALIGNED",
+            OS_BAD_MUTEX          => "OS_BAD_MUTEX",
+            OS_TIMEOUT            => "OS_TIMEOUT",
+            OS_ERR_IN_ISR         => "OS_ERR_IN_ISR",
+            OS_ERR_PRIV           => "OS_ERR_PRIV",
+            OS_NOT_STARTED        => "OS_NOT_STARTED",
+            OS_ENOENT             => "OS_ENOENT",
+            OS_EBUSY              => "OS_EBUSY",
+            OS_ERROR              => "OS_ERROR",
+            Cbor(err)             => err.name(),
+            Coap(err)             => err.name(),
+            Hal(err)              => err.name(),
+            Sensor(err)           => err.name(),
+            Other(_)              => "OTHER",
+        }
+    }
+}
+
+/// Cast `MynewtError` to `i32`
+impl From<MynewtError> for i32 {
+    /// Cast `MynewtError` to `i32`
+    fn from(err: MynewtError) -> Self {
+        err.code()
+    }
+}
+
+/// Convert a `SYS_*` or `HAL_I2C_ERR_*` error code returned by the Mynewt API to `MynewtError`.
+/// Other codes are returned as `MynewtError::Other`. For `os_error_t` codes, use `MynewtError::from_os()`.
+impl From<i32> for MynewtError {
+    /// Convert `i32` to `MynewtError`
+    fn from(num: i32) -> Self {
+        use MynewtError::*;
+        match num {
+            n if n == os::SYS_EOK as i32 => SYS_EOK,
+            os::SYS_ENOMEM    => SYS_ENOMEM,
+            os::SYS_EINVAL    => SYS_EINVAL,
+            os::SYS_ETIMEOUT  => SYS_ETIMEOUT,
+            os::SYS_ENOENT    => SYS_ENOENT,
+            os::SYS_EIO       => SYS_EIO,
+            os::SYS_EAGAIN    => SYS_EAGAIN,
+            os::SYS_EACCES    => SYS_EACCES,
+            os::SYS_EBUSY     => SYS_EBUSY,
+            os::SYS_ENODEV    => SYS_ENODEV,
+            os::SYS_ERANGE    => SYS_ERANGE,
+            os::SYS_EALREADY  => SYS_EALREADY,
+            os::SYS_ENOTSUP   => SYS_ENOTSUP,
+            os::SYS_EUNKNOWN  => SYS_EUNKNOWN,
+            os::SYS_EREMOTEIO => SYS_EREMOTEIO,
+            os::SYS_EDONE     => SYS_EDONE,
+            os::SYS_EPERUSER  => SYS_EPERUSER,
+            n if n == hal::HAL_I2C_ERR_UNKNOWN as i32   => HAL_I2C_ERR_UNKNOWN,
+            n if n == hal::HAL_I2C_ERR_INVAL as i32     => HAL_I2C_ERR_INVAL,
+            n if n == hal::HAL_I2C_ERR_TIMEOUT as i32   => HAL_I2C_ERR_TIMEOUT,
+            n if n == hal::HAL_I2C_ERR_ADDR_NACK as i32 => HAL_I2C_ERR_ADDR_NACK,
+            n if n == hal::HAL_I2C_ERR_DATA_NACK as i32 => HAL_I2C_ERR_DATA_NACK,
+            _                 => Other(num),
+        }
+    }
+}
+
+impl MynewtError {
+    /// Convert an `os_error_t` code returned by the Mynewt kernel, e.g. by `os_sem_pend()`, to `MynewtError`.
+    /// `OS_OK` becomes `SYS_EOK`. Unknown codes are returned as `MynewtError::Other`.
+    /// Not a `From` conversion, because `os_error_t` is `u32` and other codes would convert silently.
+    pub fn from_os(err: os::os_error_t) -> Self {
+        use MynewtError::*;
+        match err {
+            os::os_error_OS_OK              => SYS_EOK,
+            os::os_error_OS_ENOMEM          => OS_ENOMEM,
+            os::os_error_OS_EINVAL          => OS_EINVAL,
+            os::os_error_OS_INVALID_PARM    => OS_INVALID_PARM,
+            os::os_error_OS_MEM_NOT_ALIGNED => OS_MEM_NOT_ALIGNED,
+            os::os_error_OS_BAD_MUTEX       => OS_BAD_MUTEX,
+            os::os_error_OS_TIMEOUT         => OS_TIMEOUT,
+            os::os_error_OS_ERR_IN_ISR      => OS_ERR_IN_ISR,
+            os::os_error_OS_ERR_PRIV        => OS_ERR_PRIV,
+            os::os_error_OS_NOT_STARTED     => OS_NOT_STARTED,
+            os::os_error_OS_ENOENT          => OS_ENOENT,
+            os::os_error_OS_EBUSY           => OS_EBUSY,
+            os::os_error_OS_ERROR           => OS_ERROR,
+            _                               => Other(err as i32),
+        }
+    }
+}
+
+/// Cast `()` to `MynewtError`
+impl From<()> for MynewtError {
+    /// Cast `()` to `MynewtError`
+    fn from(_: ()) -> Self {
+        MynewtError::SYS_EUNKNOWN
+    }
+}
+
+/// Implement formatted output for MynewtError, e.g. `SYS_ETIMEOUT (-3)`.
+/// CBOR, CoAP, HAL and sensor errors are displayed with their own codes, e.g. `HAL_SPI_CONFIG (-1)`
+impl core::fmt::Display for MynewtError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        match self {
+            MynewtError::Cbor(err)   => core::fmt::Display::fmt(err, fmt),
+            MynewtError::Coap(err)   => core::fmt::Display::fmt(err, fmt),
+            MynewtError::Hal(err)    => core::fmt::Display::fmt(err, fmt),
+            MynewtError::Sensor(err) => core::fmt::Display::fmt(err, fmt),
+            _ => write!(fmt, "{} ({})", self.name(), self.code()),
+        }
+    }
+}
+
+/// Implement formatted output for MynewtError, e.g. `SYS_ETIMEOUT (-3)`
+impl core::fmt::Debug for MynewtError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// Error codes for TinyCBOR encoding and decoding, from `tinycbor::CborError_*`
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
+pub enum CborError {
+    CborUnknownError,
+    CborErrorUnknownLength,
+    CborErrorAdvancePastEOF,
+    CborErrorIO,
+    CborErrorGarbageAtEnd,
+    CborErrorUnexpectedEOF,
+    CborErrorUnexpectedBreak,
+    CborErrorUnknownType,
+    CborErrorIllegalType,
+    CborErrorIllegalNumber,
+    CborErrorIllegalSimpleType,
+    CborErrorUnknownSimpleType,
+    CborErrorUnknownTag,
+    CborErrorInappropriateTagForType,
+    CborErrorDuplicateObjectKeys,
+    CborErrorInvalidUtf8TextString,
+    CborErrorTooManyItems,
+    CborErrorTooFewItems,
+    CborErrorDataTooLarge,
+    CborErrorNestingTooDeep,
+    CborErrorUnsupportedType,
+    CborErrorJsonObjectKeyIsAggregate,
+    CborErrorJsonObjectKeyNotString,
+    CborErrorJsonNotImplemented,
+    /// Encoder buffer is full
+    CborErrorOutOfMemory,
+    CborErrorInternalError,
+    /// CBOR error code that doesn't match any of the above
+    Other(tinycbor::CborError),
+}
+
+impl CborError {
+    /// Return `Ok` if the TinyCBOR result `res` is `CborNoError`, else return the CBOR error
+    pub fn check(res: tinycbor::CborError) -> MynewtResult<()> {
+        if res == tinycbor::CborError_CborNoError { Ok(()) }
+        else { Err(MynewtError::Cbor(CborError::from(res))) }
+    }
+
+    /// Return the TinyCBOR error code
+    pub fn code(&self) -> tinycbor::CborError {
+        use CborError::*;
+        match *self {
+            CborUnknownError                  => tinycbor::CborError_CborUnknownError,
+            CborErrorUnknownLength            => tinycbor::CborError_CborErrorUnknownLength,
+            CborErrorAdvancePastEOF           => tinycbor::CborError_CborErrorAdvancePastEOF,
+            CborErrorIO                       => tinycbor::CborError_CborErrorIO,
+            CborErrorGarbageAtEnd             => tinycbor::CborError_CborErrorGarbageAtEnd,
+            CborErrorUnexpectedEOF            => tinycbor::CborError_CborErrorUnexpectedEOF,
+            CborErrorUnexpectedBreak          => tinycbor::CborError_CborErrorUnexpectedBreak,
+            CborErrorUnknownType              => tinycbor::CborError_CborErrorUnknownType,
+            CborErrorIllegalType              => tinycbor::CborError_CborErrorIllegalType,
+            CborErrorIllegalNumber            => tinycbor::CborError_CborErrorIllegalNumber,
+            CborErrorIllegalSimpleType        => tinycbor::CborError_CborErrorIllegalSimpleType,
+            CborErrorUnknownSimpleType        => tinycbor::CborError_CborErrorUnknownSimpleType,
+            CborErrorUnknownTag               => tinycbor::CborError_CborErrorUnknownTag,
+            CborErrorInappropriateTagForType  => tinycbor::CborError_CborErrorInappropriateTagForType,
+            CborErrorDuplicateObjectKeys      => tinycbor::CborError_CborErrorDuplicateObjectKeys,
+            CborErrorInvalidUtf8TextString    => tinycbor::CborError_CborErrorInvalidUtf8TextString,
+            CborErrorTooManyItems             => tinycbor::CborError_CborErrorTooManyItems,
+            CborErrorTooFewItems              => tinycbor::CborError_CborErrorTooFewItems,
+            CborErrorDataTooLarge             => tinycbor::CborError_CborErrorDataTooLarge,
+            CborErrorNestingTooDeep           => tinycbor::CborError_CborErrorNestingTooDeep,
+            CborErrorUnsupportedType          => tinycbor::CborError_CborErrorUnsupportedType,
+            CborErrorJsonObjectKeyIsAggregate => tinycbor::CborError_CborErrorJsonObjectKeyIsAggregate,
+            CborErrorJsonObjectKeyNotString   => tinycbor::CborError_CborErrorJsonObjectKeyNotString,
+            CborErrorJsonNotImplemented       => tinycbor::CborError_CborErrorJsonNotImplemented,
+            CborErrorOutOfMemory              => tinycbor::CborError_CborErrorOutOfMemory,
+            CborErrorInternalError            => tinycbor::CborError_CborErrorInternalError,
+            Other(code)                       => code,
+        }
+    }
+
+    /// Return the symbolic name of the error, e.g. `CborErrorOutOfMemory`
+    pub fn name(&self) -> &'static str {
+        use CborError::*;
+        match *self {
+            CborUnknownError                  => "CborUnknownError",
+            CborErrorUnknownLength            => "CborErrorUnknownLength",
+            CborErrorAdvancePastEOF           => "CborErrorAdvancePastEOF",
+            CborErrorIO                       => "CborErrorIO",
+            CborErrorGarbageAtEnd             => "CborErrorGarbageAtEnd",
+            CborErrorUnexpectedEOF            => "CborErrorUnexpectedEOF",
+            CborErrorUnexpectedBreak          => "CborErrorUnexpectedBreak",
+            CborErrorUnknownType              => "CborErrorUnknownType",
+            CborErrorIllegalType              => "CborErrorIllegalType",
+            CborErrorIllegalNumber            => "CborErrorIllegalNumber",
+            CborErrorIllegalSimpleType        => "CborErrorIllegalSimpleType",
+            CborErrorUnknownSimpleType        => "CborErrorUnknownSimpleType",
+            CborErrorUnknownTag               => "CborErrorUnknownTag",
+            CborErrorInappropriateTagForType  => "CborErrorInappropriateTagForType",
+            CborErrorDuplicateObjectKeys      => "CborErrorDuplicateObjectKeys",
+            CborErrorInvalidUtf8TextString    => "CborErrorInvalidUtf8TextString",
+            CborErrorTooManyItems             => "CborErrorTooManyItems",
+            CborErrorTooFewItems              => "CborErrorTooFewItems",
+            CborErrorDataTooLarge             => "CborErrorDataTooLarge",
+            CborErrorNestingTooDeep           => "CborErrorNestingTooDeep",
+            CborErrorUnsupportedType          => "CborErrorUnsupportedType",
+            CborErrorJsonObjectKeyIsAggregate => "CborErrorJsonObjectKeyIsAggregate",
+            CborErrorJsonObjectKeyNotString   => "CborErrorJsonObjectKeyNotString",
+            CborErrorJsonNotImplemented       => "CborErrorJsonNotImplemented",
+            CborErrorOutOfMemory              => "CborErrorOutOfMemory",
+            CborErrorInternalError            => "CborErrorInternalError",
+            Other(_)                          => "CborErrorOther",
+        }
+    }
+
+    /// Return the nearest `SYS_*` error code
+    fn sys_code(&self) -> i32 {
+        match *self {
+            CborError::CborErrorOutOfMemory => os::SYS_ENOMEM,
+            CborError::CborErrorIO          => os::SYS_EIO,
+            _                               => os::SYS_EINVAL,
+        }
+    }
+}
+
+/// Convert a TinyCBOR error code to `CborError`. Unknown codes are returned as `CborError::Other`.
+/// `CborNoError` should be checked with `CborError::check()` before converting.
+impl From<tinycbor::CborError> for CborError {
+    /// Convert `tinycbor::CborError` to `CborError`
+    fn from(code: tinycbor::CborError) -> Self {
+        use CborError::*;
+        match code {
+            tinycbor::CborError_CborUnknownError                  => CborUnknownError,
+            tinycbor::CborError_CborErrorUnknownLength            => CborErrorUnknownLength,
+            tinycbor::CborError_CborErrorAdvancePastEOF           => CborErrorAdvancePastEOF,
+            tinycbor::CborError_CborErrorIO                       => CborErrorIO,
+            tinycbor::CborError_CborErrorGarbageAtEnd             => CborErrorGarbageAtEnd,
+            tinycbor::CborError_CborErrorUnexpectedEOF            => CborErrorUnexpectedEOF,
+            tinycbor::CborError_CborErrorUnexpectedBreak          => CborErrorUnexpectedBreak,
+            tinycbor::CborError_CborErrorUnknownType              => CborErrorUnknownType,
+            tinycbor::CborError_CborErrorIllegalType              => CborErrorIllegalType,
+            tinycbor::CborError_CborErrorIllegalNumber            => CborErrorIllegalNumber,
+            tinycbor::CborError_CborErrorIllegalSimpleType        => CborErrorIllegalSimpleType,
+            tinycbor::CborError_CborErrorUnknownSimpleType        => CborErrorUnknownSimpleType,
+            tinycbor::CborError_CborErrorUnknownTag               => CborErrorUnknownTag,
+            tinycbor::CborError_CborErrorInappropriateTagForType  => CborErrorInappropriateTagForType,
+            tinycbor::CborError_CborErrorDuplicateObjectKeys      => CborErrorDuplicateObjectKeys,
+            tinycbor::CborError_CborErrorInvalidUtf8TextString    => CborErrorInvalidUtf8TextString,
+            tinycbor::CborError_CborErrorTooManyItems             => CborErrorTooManyItems,
+            tinycbor::CborError_CborErrorTooFewItems              => CborErrorTooFewItems,
+            tinycbor::CborError_CborErrorDataTooLarge             => CborErrorDataTooLarge,
+            tinycbor::CborError_CborErrorNestingTooDeep           => CborErrorNestingTooDeep,
+            tinycbor::CborError_CborErrorUnsupportedType          => CborErrorUnsupportedType,
+            tinycbor::CborError_CborErrorJsonObjectKeyIsAggregate => CborErrorJsonObjectKeyIsAggregate,
+            tinycbor::CborError_CborErrorJsonObjectKeyNotString   => CborErrorJsonObjectKeyNotString,
+            tinycbor::CborError_CborErrorJsonNotImplemented       => CborErrorJsonNotImplemented,
+            tinycbor::CborError_CborErrorOutOfMemory              => CborErrorOutOfMemory,
+            tinycbor::CborError_CborErrorInternalError            => CborErrorInternalError,
+            _                                                     => Other(code),
+        }
+    }
+}
+
+/// Error codes for CoAP payload encoding failure
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
+pub enum CoapError {
+    /// No error
+    OK = 0,
+    /// Encoded value is not unsigned integer
+    VALUE_NOT_UINT = 1,
+}
+
+/// No error by default
+impl Default for CoapError {
+    fn default() -> Self {
+        CoapError::OK
+    }
+}
+
+impl CoapError {
+    /// Return the symbolic name of the error, e.g. `VALUE_NOT_UINT`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CoapError::OK             => "OK",
+            CoapError::VALUE_NOT_UINT => "VALUE_NOT_UINT",
+        }
+    }
+}
+
+/// SPI and GPIO HAL failures. Contains the return code from the HAL function that failed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HalError {
+    /// `hal_spi_config()` failed
+    SpiConfig(i32),
+    /// `hal_spi_enable()` failed
+    SpiEnable(i32),
+    /// `hal_spi_disable()` failed
+    SpiDisable(i32),
+    /// `hal_spi_set_txrx_cb()` failed
+    SpiSetCallback(i32),
+    /// `hal_spi_txrx()` or `hal_spi_txrx_noblock()` failed
+    SpiTransfer(i32),
+    /// `hal_gpio_init_out()` or `hal_gpio_init_in()` failed
+    GpioInit(i32),
+}
+
+impl HalError {
+    /// Return `Ok` if the HAL return code `rc` is 0, else return the HAL error created by `err`, e.g.
+    /// `HalError::check(rc, HalError::SpiConfig) ? ;`
+    pub fn check(rc: i32, err: fn(i32) -> HalError) -> MynewtResult<()> {
+        if rc == 0 { Ok(()) }
+        else { Err(MynewtError::Hal(err(rc))) }
+    }
+
+    /// Return the return code from the HAL function that failed
+    pub fn code(&self) -> i32 {
+        match *self {
+            HalError::SpiConfig(rc)      => rc,
+            HalError::SpiEnable(rc)      => rc,
+            HalError::SpiDisable(rc)     => rc,
+            HalError::SpiSetCallback(rc) => rc,
+            HalError::SpiTransfer(rc)    => rc,
+            HalError::GpioInit(rc)       => rc,
+        }
+    }
+
+    /// Return the symbolic name of the error, e.g. `HAL_SPI_CONFIG`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HalError::SpiConfig(_)      => "HAL_SPI_CONFIG",
+            HalError::SpiEnable(_)      => "HAL_SPI_ENABLE",
+            HalError::SpiDisable(_)     => "HAL_SPI_DISABLE",
+            HalError::SpiSetCallback(_) => "HAL_SPI_SET_CALLBACK",
+            HalError::SpiTransfer(_)    => "HAL_SPI_TRANSFER",
+            HalError::GpioInit(_)       => "HAL_GPIO_INIT",
+        }
+    }
+}
+
+/// Sensor and Sensor Listener failures
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SensorError {
+    /// No sensor matches the device name
+    NotFound,
+    /// Sensor data is missing, or the sensor is not ready
+    InvalidData,
+    /// Sensor type is not supported. Contains the `sensor_type_t`.
+    UnsupportedType(i64),
+    /// Too many sensor listeners. Increase `MAX_SENSOR_LISTENERS`
+    TooManyListeners,
+    /// The internal sensor listener is already registered
+    ListenerInUse,
+}
+
+impl SensorError {
+    /// Return the symbolic name of the error, e.g. `SENSOR_NOT_FOUND`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SensorError::NotFound           => "SENSOR_NOT_FOUND",
+            SensorError::InvalidData        => "SENSOR_INVALID_DATA",
+            SensorError::UnsupportedType(_) => "SENSOR_UNSUPPORTED_TYPE",
+            SensorError::TooManyListeners   => "SENSOR_TOO_MANY_LISTENERS",
+            SensorError::ListenerInUse      => "SENSOR_LISTENER_IN_USE",
+        }
+    }
+
+    /// Return the nearest `SYS_*` error code
+    fn sys_code(&self) -> i32 {
+        match *self {
+            SensorError::NotFound           => os::SYS_ENODEV,
+            SensorError::InvalidData        => os::SYS_EINVAL,
+            SensorError::UnsupportedType(_) => os::SYS_ENOTSUP,
+            SensorError::TooManyListeners   => os::SYS_ENOMEM,
+            SensorError::ListenerInUse      => os::SYS_EBUSY,
+        }
+    }
+}
+
+/// Wrap `CborError` as `MynewtError`, so that `?` works
+impl From<CborError> for MynewtError {
+    fn from(err: CborError) -> Self { MynewtError::Cbor(err) }
+}
+
+/// Wrap `CoapError` as `MynewtError`, so that `?` works
+impl From<CoapError> for MynewtError {
+    fn from(err: CoapError) -> Self { MynewtError::Coap(err) }
+}
+
+/// Wrap `HalError` as `MynewtError`, so that `?` works
+impl From<HalError> for MynewtError {
+    fn from(err: HalError) -> Self { MynewtError::Hal(err) }
+}
+
+/// Wrap `SensorError` as `MynewtError`, so that `?` works
+impl From<SensorError> for MynewtError {
+    fn from(err: SensorError) -> Self { MynewtError::Sensor(err) }
+}
+
+/// Implement formatted output for CborError, e.g. `CborErrorOutOfMemory (2147483648)`
+impl core::fmt::Display for CborError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        write!(fmt, "{} ({})", self.name(), self.code())
+    }
+}
+
+/// Implement formatted output for CborError, e.g. `CborErrorOutOfMemory (2147483648)`
+impl core::fmt::Debug for CborError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// Implement formatted output for CoapError, e.g. `VALUE_NOT_UINT (1)`
+impl core::fmt::Display for CoapError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        write!(fmt, "{} ({})", self.name(), *self as i32)
+    }
+}
+
+/// Implement formatted output for CoapError, e.g. `VALUE_NOT_UINT (1)`
+impl core::fmt::Debug for CoapError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// Implement formatted output for HalError, e.g. `HAL_SPI_CONFIG (-1)`
+impl core::fmt::Display for HalError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        write!(fmt, "{} ({})", self.name(), self.code())
+    }
+}
+
+/// Implement formatted output for HalError, e.g. `HAL_SPI_CONFIG (-1)`
+impl core::fmt::Debug for HalError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// Implement formatted output for SensorError, e.g. `SENSOR_UNSUPPORTED_TYPE (4096)`
+impl core::fmt::Display for SensorError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        match *self {
+            SensorError::UnsupportedType(sensor_type) => write!(fmt, "{} ({})", self.name(), sensor_type),
+            _ => fmt.write_str(self.name()),
+        }
+    }
+}
+
+/// Implement formatted output for SensorError, e.g. `SENSOR_UNSUPPORTED_TYPE (4096)`
+impl core::fmt::Debug for SensorError {
+    fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        core::fmt::Display::fmt(self, fmt)
+    }
+}
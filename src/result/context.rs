@@ -0,0 +1,165 @@
+//! Error context for `MynewtResult`. Records the operation and call site of each error
+//! passed through `.context()`, in a fixed-size trail that may be displayed with `print_trail()`.
+//! Enabled by the `error_context` feature. When the feature is disabled, `.context()` returns
+//! the result unchanged and the trail is always empty.
+
+use core::{
+    cell::RefCell,
+    fmt::Write,
+    panic::Location,
+};
+use crate::{
+    kernel::critical::{ critical_section, IrqMutex },
+    sys::console,
+};
+use super::{ MynewtError, MynewtResult };
+
+/// Max number of frames in the error trail
+#[cfg(feature = "error_context")]
+pub const ERROR_TRAIL_SIZE: usize = 8;
+
+/// Max number of frames in the error trail. Nothing is recorded when `error_context` is disabled.
+#[cfg(not(feature = "error_context"))]
+pub const ERROR_TRAIL_SIZE: usize = 0;
+
+/// Add call-site context to a `MynewtResult`
+pub trait ResultContext<T> {
+    /// If this is an error, record the operation `op` and the caller's location in the error trail.
+    /// Returns the result unchanged.
+    /// ```
+    /// spi_noblock_write(cmd, data).context("spi enqueue") ? ;
+    /// ```
+    fn context(self, op: &'static str) -> MynewtResult<T>;
+}
+
+/// Add call-site context to a `MynewtResult`
+impl<T> ResultContext<T> for MynewtResult<T> {
+    /// Record the operation `op` and the caller's location in the error trail
+    #[cfg(feature = "error_context")]
+    #[track_caller]
+    fn context(self, op: &'static str) -> MynewtResult<T> {
+        if let Err(err) = &self {
+            record(ErrorFrame { op, location: Location::caller(), error: *err });
+        }
+        self
+    }
+
+    /// Context is not recorded when `error_context` is disabled
+    #[cfg(not(feature = "error_context"))]
+    #[inline(always)]
+    fn context(self, _op: &'static str) -> MynewtResult<T> {
+        self
+    }
+}
+
+/// Operation and call site that an error passed through
+#[derive(Clone, Copy)]
+pub struct ErrorFrame {
+    /// Operation that failed, e.g. `spi enqueue`
+    pub op: &'static str,
+    /// Source location that called `.context()`
+    pub location: &'static Location<'static>,
+    /// Error returned by the operation
+    pub error: MynewtError,
+}
+
+/// Trail of call sites that the last error passed through, starting from the innermost call site
+#[derive(Clone, Copy)]
+pub struct ErrorTrail {
+    /// Recorded frames. Only the first `len` frames are valid.
+    frames: [Option<ErrorFrame>; ERROR_TRAIL_SIZE],
+    /// Number of recorded frames
+    len: usize,
+    /// Number of frames that were not recorded because the trail is full. Stops counting at `u16::MAX`.
+    dropped: u16,
+    /// Task that recorded the last frame
+    #[cfg(feature = "error_context")]
+    task: usize,
+}
+
+impl ErrorTrail {
+    /// Return an empty error trail
+    const fn new() -> Self {
+        ErrorTrail {
+            frames:  [None; ERROR_TRAIL_SIZE],
+            len:     0,
+            dropped: 0,
+            #[cfg(feature = "error_context")]
+            task:    0,
+        }
+    }
+
+    /// Return the recorded frames, starting from the innermost call site
+    pub fn frames(&self) -> impl Iterator<Item = &ErrorFrame> {
+        self.frames[..self.len].iter().filter_map(|frame| frame.as_ref())
+    }
+
+    /// Return the number of frames that were not recorded because the trail is full
+    pub fn dropped(&self) -> usize {
+        self.dropped as usize
+    }
+
+    /// Return true if no frames were recorded
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Error trail for the last error passed through `.context()`. May be updated by multiple tasks.
+static ERROR_TRAIL: IrqMutex<RefCell<ErrorTrail>> = IrqMutex::new(RefCell::new(ErrorTrail::new()));
+
+/// Append the frame to the error trail. An error bubbles up through the callers in the same task, so start
+/// a new trail if the frame comes from a different task or has a different error from the trail.
+/// Two failures in a row with the same error in the same task are recorded in the same trail,
+/// call `clear_trail()` after handling an error to keep them apart.
+#[cfg(feature = "error_context")]
+fn record(frame: ErrorFrame) {
+    let task = unsafe { crate::kernel::os::os_sched_get_current_task() } as usize;
+    ERROR_TRAIL.lock(|trail| {
+        if trail.len > 0 && (
+            trail.task != task ||
+            trail.frames[trail.len - 1].map(|f| f.error) != Some(frame.error)
+        ) {
+            *trail = ErrorTrail::new();  //  This is a new error, start a new trail.
+        }
+        trail.task = task;
+        if trail.len < ERROR_TRAIL_SIZE {
+            trail.frames[trail.len] = Some(frame);
+            trail.len += 1;
+        } else {
+            //  Keep the innermost frames, which are closest to the failure.
+            trail.dropped = trail.dropped.saturating_add(1);
+        }
+    });
+}
+
+/// Return a copy of the error trail for the last error passed through `.context()`
+pub fn trail() -> ErrorTrail {
+    critical_section(|cs| *ERROR_TRAIL.borrow(cs).borrow())
+}
+
+/// Erase the error trail, e.g. after the error has been handled
+pub fn clear_trail() {
+    ERROR_TRAIL.lock(|trail| *trail = ErrorTrail::new());
+}
+
+/// Display the error trail on the console, like this:
+/// ```
+/// error trail:
+///   spi enqueue at src/spi.rs:167: SYS_ENOMEM (-1)
+///   spi flush at src/display.rs:52: SYS_ENOMEM (-1)
+/// ```
+/// Messages are added to the console output buffer, call `console::flush()` to display them.
+pub fn print_trail() {
+    let trail = trail();
+    if trail.is_empty() { return; }
+    let mut out = console::Console;
+    let _ = writeln!(out, "error trail:");
+    for frame in trail.frames() {
+        let _ = writeln!(out, "  {} at {}:{}: {}",
+            frame.op, frame.location.file(), frame.location.line(), frame.error);
+    }
+    if trail.dropped() > 0 {
+        let _ = writeln!(out, "  ({} more)", trail.dropped());
+    }
+}
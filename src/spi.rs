@@ -4,9 +4,22 @@ use crate::{
     self as mynewt,
     result::*,
     hw::hal,
-    kernel::os,
+    kernel::{
+        event::EventQueue,
+        mbuf::Mbuf,
+        memstats,
+        mqueue::MQueue,
+        sanity::{ self, SanityConfig },
+        sync::{ Mutex, Semaphore },
+        task::Task,
+        time::{ self, Duration },
+    },
+    sys::console,
     NULL, Ptr, Strn,
 };
+use core::fmt::Write;
+#[cfg(feature = "async")]
+use crate::kernel::executor;
 use mynewt_macros::{
     init_strn,
 };
@@ -35,35 +48,48 @@ type PendingCmdSize = heapless::consts::U1;
 /// Max size of pending Data Bytes
 type PendingDataSize = heapless::consts::U8192;
 
-/// Pending SPI Command Byte to be written
-static mut PENDING_CMD: heapless::Vec<u8, PendingCmdSize> = heapless::Vec(heapless::i::Vec::new());
-/// Pending SPI Data Bytes to be written
-static mut PENDING_DATA: heapless::Vec<u8, PendingDataSize> = heapless::Vec(heapless::i::Vec::new());
+/// Pending SPI request to be written
+struct PendingRequest {
+    /// Pending SPI Command Byte to be written
+    cmd:  heapless::Vec<u8, PendingCmdSize>,
+    /// Pending SPI Data Bytes to be written
+    data: heapless::Vec<u8, PendingDataSize>,
+}
+
+/// Pending SPI request, locked by the task that is writing the request
+static PENDING: Mutex<PendingRequest> = Mutex::new(PendingRequest {
+    cmd:  heapless::Vec(heapless::i::Vec::new()),
+    data: heapless::Vec(heapless::i::Vec::new()),
+});
+
+/// Semaphore that is signalled for every completed SPI request. Init to 0 tokens, so caller will block until SPI request is completed.
+static SPI_SEM: Semaphore = Semaphore::new(0);
 
-/// Semaphore that is signalled for every completed SPI request
-static mut SPI_SEM: os::os_sem = fill_zero!(os::os_sem);
+/// Semaphore that throttles the number of queued SPI requests. Only max 2 requests queued, the next request will block.
+static SPI_THROTTLE_SEM: Semaphore = Semaphore::new(2);
 
-/// Semaphore that throttles the number of queued SPI requests
-static mut SPI_THROTTLE_SEM: os::os_sem = fill_zero!(os::os_sem);
+/// Max time to wait for the SPI throttle and for each SPI transfer to complete
+const SPI_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// SPI Task must check in with the sanity task within this interval. Longer than `SPI_TIMEOUT`, so that a slow transfer is not reported as hung.
+const SPI_SANITY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Max time that the SPI Task waits for a request before checking in with the sanity task
+const SPI_IDLE_TIMEOUT: Duration = Duration::from_secs(20);
 
 /// Mbuf Queue that contains the SPI data packets to be sent. Why use Mbuf Queue? 
 /// Because it's a Mynewt OS low-level buffer that allows packets of various sizes to be copied efficiently.
-static mut SPI_DATA_QUEUE: os::os_mqueue = fill_zero!(os::os_mqueue);
+/// `spi_request_handler()` is called in the SPI Task for each queued request.
+static SPI_DATA_QUEUE: MQueue<()> = MQueue::new(&SPI_EVENT_QUEUE, spi_request_handler, ());
 
 /// Event Queue that contains the pending non-blocking SPI requests
-static mut SPI_EVENT_QUEUE: os::os_eventq = fill_zero!(os::os_eventq);
+static SPI_EVENT_QUEUE: EventQueue = EventQueue::new();
 
 /// SPI Task that will send each SPI request sequentially
-static mut SPI_TASK: os::os_task = fill_zero!(os::os_task);
-
-/// Stack space for SPI Task, initialised to 0.
-static mut SPI_TASK_STACK: [os::os_stack_t; SPI_TASK_STACK_SIZE] = 
-    [0; SPI_TASK_STACK_SIZE];
+static SPI_TASK: Task<(), SPI_TASK_STACK_SIZE> = Task::new();
 
 /// Size of the stack (in 4-byte units). Previously `OS_STACK_ALIGN(256)`  
 const SPI_TASK_STACK_SIZE: usize = 256;
-//  TODO: Get this constant from Mynewt
-const OS_TICKS_PER_SEC: u32 = 128;
 
 /// Non-blocking SPI transfer callback parameter (not used)
 struct SpiCallback {}
@@ -77,70 +103,53 @@ pub fn spi_noblock_init() -> MynewtResult<()> {
     unsafe { hal::hal_spi_disable(SPI_NUM) };
 
     //  Configure SPI port for non-blocking SPI
-    let rc = unsafe { hal::hal_spi_config(SPI_NUM, &mut SPI_SETTINGS) }; assert_eq!(rc, 0, "spi config fail");  //  TODO: Map to MynewtResult
+    let rc = unsafe { hal::hal_spi_config(SPI_NUM, &mut SPI_SETTINGS) }; HalError::check(rc, HalError::SpiConfig) ? ;
     let arg = unsafe { core::mem::transmute(&mut SPI_CALLBACK) };
     let rc = unsafe { hal::hal_spi_set_txrx_cb(
         SPI_NUM, 
         Some(spi_noblock_handler), 
         arg
     ) };
-    assert_eq!(rc, 0, "spi cb fail");  //  TODO: Map to MynewtResult
+    HalError::check(rc, HalError::SpiSetCallback) ? ;
 
     //  Enable SPI port and set SS to high to disable SPI device
-    let rc = unsafe { hal::hal_spi_enable(SPI_NUM) }; assert_eq!(rc, 0, "spi enable fail");  //  TODO: Map to MynewtResult
-    let rc = unsafe { hal::hal_gpio_init_out(SPI_SS_PIN, 1) }; assert_eq!(rc, 0, "gpio fail");  //  TODO: Map to MynewtResult
-    let rc = unsafe { hal::hal_gpio_init_out(SPI_DC_PIN, 1) }; assert_eq!(rc, 0, "gpio fail");  //  TODO: Map to MynewtResult
-
-    //  Create Event Queue and Mbuf (Data) Queue that will store the SPI requests
-    unsafe { os::os_eventq_init(&mut SPI_EVENT_QUEUE) };
-    let rc = unsafe { os::os_mqueue_init(
-        &mut SPI_DATA_QUEUE, 
-        Some(spi_event_callback), 
-        NULL
-    ) };
-    assert_eq!(rc, 0, "mqueue fail");  //  TODO: Map to MynewtResult
+    let rc = unsafe { hal::hal_spi_enable(SPI_NUM) }; HalError::check(rc, HalError::SpiEnable) ? ;
+    let rc = unsafe { hal::hal_gpio_init_out(SPI_SS_PIN, 1) }; HalError::check(rc, HalError::GpioInit) ? ;
+    let rc = unsafe { hal::hal_gpio_init_out(SPI_DC_PIN, 1) }; HalError::check(rc, HalError::GpioInit) ? ;
 
-    //  Create the Semaphore that will signal whether the SPI request has completed
-    let rc = unsafe { os::os_sem_init(&mut SPI_SEM, 0) };  //  Init to 0 tokens, so caller will block until SPI request is completed.
-    assert_eq!(rc, 0, "sem fail");  //  TODO: Map to MynewtResult
+    //  Mbuf (Data) Queue that will store the SPI requests and its Event Queue are initialised when first used.
 
-    //  Create the Semaphore that will throttle the number of queued SPI requests
-    let rc = unsafe { os::os_sem_init(&mut SPI_THROTTLE_SEM, 2) };  //  Only max 2 requests queued, the next request will block
-    assert_eq!(rc, 0, "sem fail");  //  TODO: Map to MynewtResult
-    
     //  Create a task to send SPI requests sequentially from the SPI Event Queue and Mbuf Queue
-    os::task_init(                //  Create a new task and start it...
-        unsafe { &mut SPI_TASK }, //  Task object will be saved here
-        &init_strn!( "spi" ),     //  Name of task
-        Some( spi_task_func ),    //  Function to execute when task starts
-        NULL,  //  Argument to be passed to above function
-        10,    //  Task priority: highest is 0, lowest is 255 (main task is 127)
-        os::OS_WAIT_FOREVER as u32,     //  Don't do sanity / watchdog checking
-        unsafe { &mut SPI_TASK_STACK }, //  Stack space for the task
-        SPI_TASK_STACK_SIZE as u16      //  Size of the stack (in 4-byte units)
-    ) ? ;                               //  `?` means check for error
+    SPI_TASK.start_with_sanity(  //  Create a new task and start it...
+        &init_strn!( "spi" ),    //  Name of task
+        10,                      //  Task priority: highest is 0, lowest is 255 (main task is 127)
+        spi_task_func,           //  Function to execute when task starts
+        (),                      //  Argument to be passed to above function
+        SanityConfig::new(SPI_SANITY_INTERVAL)  //  Task must check in within this interval
+    ) ? ;                        //  `?` means check for error
     Ok(())
 }
 
-/// SPI Task Function.  Execute sequentially each SPI request posted to our Event Queue.  When there are no requests to process, block until one arrives.
-extern "C" fn spi_task_func(_arg: Ptr) {
+/// SPI Task Function.  Execute sequentially each SPI request posted to our Event Queue.  When there are no requests to process, block until one arrives or the idle timeout expires.
+fn spi_task_func(_arg: ()) {
     loop {
-        //  Forever read SPI requests and execute them. Will call spi_event_callback().
-        os::eventq_run(
-            unsafe { &mut SPI_EVENT_QUEUE }
-        ).expect("eventq fail");
+        //  Forever read SPI requests and execute them. Will call spi_request_handler().
+        if let Some(event) = SPI_EVENT_QUEUE.poll(SPI_IDLE_TIMEOUT) {
+            event.run();
+        }
 
-        //  Tickle the watchdog so that the Watchdog Timer doesn't expire. Mynewt assumes the process is hung if we don't tickle the watchdog.
-        unsafe { hal_watchdog_tickle() };
+        //  Check in with the sanity task, which tickles the watchdog. Mynewt assumes the task is hung if we don't check in.
+        sanity::checkin().expect("sanity fail");
     }
 }
 
 /// Set pending request for non-blocking SPI write for Command Byte. Returns without waiting for write to complete.
 pub fn spi_noblock_write_command(cmd: u8) -> MynewtResult<()> {
     //  If there is a pending Command Byte, enqueue it.
-    spi_noblock_write_flush() ? ;
+    spi_noblock_write_flush().context("spi flush") ? ;
     //  Set the pending Command Byte.
-    if unsafe { PENDING_CMD.push(cmd).is_err() } {
+    let mut pending = PENDING.lock(SPI_TIMEOUT) ? ;
+    if pending.cmd.push(cmd).is_err() {
         return Err(MynewtError::SYS_EINVAL);
     }
     Ok(())
@@ -148,39 +157,71 @@ pub fn spi_noblock_write_command(cmd: u8) -> MynewtResult<()> {
 
 /// Set pending request for non-blocking SPI write for Data Bytes. Returns without waiting for write to complete.
 pub fn spi_noblock_write_data(data: &[u8]) -> MynewtResult<()> {
-    assert!(unsafe { PENDING_CMD.len() } > 0, "no cmd byte");  //  Must have Command Byte before Data Bytes
-    //  if unsafe { PENDING_DATA.len() + data.len() > PENDING_DATA.capacity() } { cortex_m::asm::bkpt(); } ////
-    assert!(unsafe { PENDING_DATA.len() + data.len() <= PENDING_DATA.capacity() }, "spi overflow");
+    let mut pending = PENDING.lock(SPI_TIMEOUT) ? ;
+    assert!(pending.cmd.len() > 0, "no cmd byte");  //  Must have Command Byte before Data Bytes
+    assert!(pending.data.len() + data.len() <= pending.data.capacity(), "spi overflow");
     //  Append Data Bytes to Pending Data Bytes.
-    unsafe { PENDING_DATA.extend_from_slice(data) } ? ;
+    pending.data.extend_from_slice(data) ? ;
     Ok(())
 }
 
 /// Enqueue any pending request for non-blocking SPI write for Command Byte and Data Bytes. Returns without waiting for write to complete.
 pub fn spi_noblock_write_flush() -> MynewtResult<()> {
-    //  If no pending request, quit.
-    if unsafe { PENDING_CMD.len() } == 0 &&
-        unsafe { PENDING_DATA.len() } == 0 {
-        return Ok(());
+    //  Throttle the number of queued SPI requests. Fail if the SPI task is stuck.
+    //  Take the throttle before locking the pending request, so that the lock is not held while waiting.
+    SPI_THROTTLE_SEM.pend(SPI_TIMEOUT).context("spi throttle") ? ;
+    let mut pending = match PENDING.lock(SPI_TIMEOUT) {
+        Ok(pending) => pending,
+        Err(err) => { SPI_THROTTLE_SEM.release().ok(); return Err(err); }  //  Release the throttle, keep the original error
+    };
+    //  If no pending request, release the throttle and quit.
+    if pending.cmd.len() == 0 &&
+        pending.data.len() == 0 {
+        drop(pending);
+        return SPI_THROTTLE_SEM.release();
     }
     //  Enqueue the pending SPI request into the Mbuf Queue
-    if let Err(e) = spi_noblock_write(
-        unsafe { PENDING_CMD[0] },  //  Command Byte
-        unsafe { &PENDING_DATA }    //  Data Bytes
-    ) {  //  In case of error, clear the pending request and return error.        
-        unsafe { PENDING_CMD.clear() };
-        unsafe { PENDING_DATA.clear() };    
-        return Err(e);
+    let res = enqueue_request(
+        pending.cmd[0],  //  Command Byte
+        &pending.data    //  Data Bytes
+    ).context("spi enqueue");
+    //  Clear the pending request, even in case of error. Return the error if any.
+    pending.cmd.clear();
+    pending.data.clear();
+    res
+}
+
+/// Async version of `spi_noblock_write_flush()`. Waits for the SPI throttle without blocking the async task's executor.
+#[cfg(feature = "async")]
+pub async fn spi_noblock_write_flush_async() -> MynewtResult<()> {
+    //  Take the throttle before locking the pending request, so that the lock is not held while waiting.
+    executor::with_timeout(SPI_TIMEOUT, SPI_THROTTLE_SEM.pend_async()).await
+        .and_then(|res| res)
+        .context("spi throttle") ? ;
+    let mut pending = match executor::with_timeout(SPI_TIMEOUT, PENDING.lock_async()).await.and_then(|res| res) {
+        Ok(pending) => pending,
+        Err(err) => { SPI_THROTTLE_SEM.release().ok(); return Err(err); }  //  Release the throttle, keep the original error
+    };
+    //  If no pending request, release the throttle and quit.
+    if pending.cmd.len() == 0 &&
+        pending.data.len() == 0 {
+        drop(pending);
+        return SPI_THROTTLE_SEM.release();
     }
-    //  Else clear the pending request and return Ok.
-    unsafe { PENDING_CMD.clear() };
-    unsafe { PENDING_DATA.clear() };
-    Ok(())
+    //  Enqueue the pending SPI request into the Mbuf Queue
+    let res = enqueue_request(
+        pending.cmd[0],  //  Command Byte
+        &pending.data    //  Data Bytes
+    ).context("spi enqueue");
+    //  Clear the pending request, even in case of error. Return the error if any.
+    pending.cmd.clear();
+    pending.data.clear();
+    res
 }
 
-/// Enqueue request for non-blocking SPI write. Returns without waiting for write to complete.
-/// Request must have a Command Byte, followed by optional Data Bytes.
-fn spi_noblock_write(cmd: u8, data: &[u8]) -> MynewtResult<()> {
+/// Enqueue request for non-blocking SPI write. The caller must have taken a token from the SPI throttle,
+/// which is released if the request can't be queued.
+fn enqueue_request(cmd: u8, data: &[u8]) -> MynewtResult<()> {
     /* Dump the SPI request
     console::print("spi cmd "); ////
     console::dump(&cmd, 1 as u32); console::print("\n"); ////
@@ -188,115 +229,85 @@ fn spi_noblock_write(cmd: u8, data: &[u8]) -> MynewtResult<()> {
     console::dump(data.as_ptr(), data.len() as u32); console::print("\n"); ////
     console::flush(); */
 
-    //  Throttle the number of queued SPI requests.
-    let timeout = 30_000;
-    unsafe { os::os_sem_pend(&mut SPI_THROTTLE_SEM, timeout * OS_TICKS_PER_SEC / 1000) };
-
-    //  Allocate a new mbuf chain to copy the data to be sent.
-    let len = data.len() as u16 + 1;  //  1 Command Byte + Multiple Data Bytes
-    let mbuf = unsafe { os::os_msys_get_pkthdr(len, 0) };
-    if mbuf.is_null() {  //  If out of memory, quit.
-        unsafe { os::os_sem_release(&mut SPI_THROTTLE_SEM) };  //  Release the throttle
-        return Err(MynewtError::SYS_ENOMEM); 
-    }
+    //  Copy the Command Byte and Data Bytes into a new mbuf chain. Release the throttle if out of memory.
+    let mbuf = match new_request(cmd, data) {
+        Ok(mbuf) => mbuf,
+        Err(err) => {
+            memstats::check_alarms();       //  Report the pools that ran dry
+            SPI_THROTTLE_SEM.release().ok();  //  Release the throttle, keep the original error
+            return Err(err);
+        }
+    };
 
-    //  Append the Command Byte to the mbuf chain.
-    let rc = unsafe { os::os_mbuf_append(
-        mbuf, 
-        core::mem::transmute(&cmd), 
-        1
-    ) };
-    if rc != 0 {  //  If out of memory, quit.
-        unsafe { os::os_mbuf_free_chain(mbuf) };               //  Deallocate the mbuf chain
-        unsafe { os::os_sem_release(&mut SPI_THROTTLE_SEM) };  //  Release the throttle
-        return Err(MynewtError::SYS_ENOMEM); 
+    //  Add the mbuf to the SPI Mbuf Queue and trigger an event in the SPI Event Queue.
+    if let Err(err) = SPI_DATA_QUEUE.put(mbuf) {  //  If failed, quit. The mbuf chain has been freed.
+        SPI_THROTTLE_SEM.release().ok();  //  Release the throttle, keep the original error
+        return Err(err); 
     }
+    Ok(())
+}
 
+/// Allocate a new mbuf chain containing the Command Byte followed by the Data Bytes
+fn new_request(cmd: u8, data: &[u8]) -> MynewtResult<Mbuf> {
+    let len = data.len() as u16 + 1;  //  1 Command Byte + Multiple Data Bytes
+    let mut mbuf = Mbuf::new_pkthdr(len) ? ;
+    mbuf.append(&[cmd]) ? ;
     //  Append the Data Bytes to the mbuf chain.  This may increase the number of mbufs in the chain.
-    let rc = unsafe { os::os_mbuf_append(
-        mbuf, 
-        core::mem::transmute(data.as_ptr()), 
-        data.len() as u16
-    ) };
-    if rc != 0 {  //  If out of memory, quit.
-        unsafe { os::os_mbuf_free_chain(mbuf) };               //  Deallocate the mbuf chain
-        unsafe { os::os_sem_release(&mut SPI_THROTTLE_SEM) };  //  Release the throttle
-        return Err(MynewtError::SYS_ENOMEM); 
-    }
+    mbuf.append(data) ? ;
+    Ok(mbuf)
+}
 
-    //  Add the mbuf to the SPI Mbuf Queue and trigger an event in the SPI Event Queue.
-    let rc = unsafe { os::os_mqueue_put(
-        &mut SPI_DATA_QUEUE, 
-        &mut SPI_EVENT_QUEUE, 
-        mbuf
-    ) };
-    if rc != 0 {  //  If out of memory, quit.
-        unsafe { os::os_mbuf_free_chain(mbuf) };               //  Deallocate the mbuf chain
-        unsafe { os::os_sem_release(&mut SPI_THROTTLE_SEM) };  //  Release the throttle
-        return Err(MynewtError::SYS_EUNKNOWN); 
+/// Called by the SPI Task for each SPI request in the Mbuf Queue. The request is an mbuf chain
+/// containing the Command Byte followed by the Data Bytes. If the transfer fails, the rest of the request is dropped.
+fn spi_request_handler(_context: &'static (), mbuf: Mbuf) {
+    //  Send the mbuf chain.
+    if let Err(err) = send_request(&mbuf) {
+        //  Set the SS Pin to high to stop the transfer.
+        unsafe { hal::hal_gpio_write(SPI_SS_PIN, 1) };
+        //  Drain any completion signalled after the timeout, so that the next transfer doesn't proceed too early.
+        while SPI_SEM.try_pend().is_ok() {}
+        let _ = writeln!(console::Console, "spi fail: {}", err);
+        console::flush();
     }
-    Ok(())
+    //  Free the entire mbuf chain.
+    drop(mbuf);
+
+    //  Release the throttle semaphore to allow next request to be queued.
+    SPI_THROTTLE_SEM.release().expect("sem fail");
 }
 
-/// Callback for the event that is triggered when an SPI request is added to the queue.
-extern "C" fn spi_event_callback(_event: *mut os::os_event) {    
-    loop {  //  For each mbuf chain found...
-        //  Get the next SPI request, stored as an mbuf chain.
-        let om = unsafe { os::os_mqueue_get(&mut SPI_DATA_QUEUE) };
-        if om.is_null() { break; }
-
-        //  Send the mbuf chain.
-        let mut m = om;
-        let mut first_byte = true;
-        while !m.is_null() {  //  For each mbuf in the chain...
-            let data = unsafe { (*m).om_data };  //  Fetch the data
-            let len = unsafe { (*m).om_len };    //  Fetch the length
-            if first_byte {  //  First byte of the mbuf chain is always Command Byte
-                first_byte = false;
-                //  Write the Command Byte.
-                internal_spi_noblock_write(
-                    unsafe { core::mem::transmute(data) }, 
-                    1 as i32,          //  Write 1 Command Byte
-                    true
-                ).expect("int spi fail");
-
-                //  These commands require a delay. TODO: Move to caller
-                if  unsafe { *data } == 0x01 || //  SWRESET
-                    unsafe { *data } == 0x11 || //  SLPOUT
-                    unsafe { *data } == 0x29 {  //  DISPON
-                    delay_ms(200);
-                }
-
-                //  Then write the Data Bytes.
-                internal_spi_noblock_write(
-                    unsafe { core::mem::transmute(data.add(1)) }, 
-                    (len - 1) as i32,  //  Then write 0 or more Data Bytes
-                    false
-                ).expect("int spi fail");
-
-            } else {  //  Second and subsequently mbufs in the chain are all Data Bytes
-                //  Write the Data Bytes.
-                internal_spi_noblock_write(
-                    unsafe { core::mem::transmute(data) }, 
-                    len as i32,  //  Write all Data Bytes
-                    false
-                ).expect("int spi fail");
+/// Send the SPI request in the mbuf chain: the Command Byte followed by the Data Bytes
+fn send_request(mbuf: &Mbuf) -> MynewtResult<()> {
+    let mut first_byte = true;
+    for data in mbuf.segments() {  //  For each mbuf in the chain...
+        if data.is_empty() { continue; }
+        if first_byte {  //  First byte of the mbuf chain is always Command Byte
+            first_byte = false;
+            //  Write the Command Byte.
+            internal_spi_noblock_write(&data[..1], true) ? ;
+
+            //  These commands require a delay. TODO: Move to caller
+            if  data[0] == 0x01 || //  SWRESET
+                data[0] == 0x11 || //  SLPOUT
+                data[0] == 0x29 {  //  DISPON
+                time::delay(Duration::from_millis(200));
             }
-            m = unsafe { (*m).om_next.sle_next };  //  Fetch next mbuf in the chain.
-        }
-        //  Free the entire mbuf chain.
-        unsafe { os::os_mbuf_free_chain(om) };
 
-        //  Release the throttle semaphore to allow next request to be queued.
-        let rc = unsafe { os::os_sem_release(&mut SPI_THROTTLE_SEM) };
-        assert_eq!(rc, 0, "sem fail");    
+            //  Then write 0 or more Data Bytes.
+            internal_spi_noblock_write(&data[1..], false) ? ;
+
+        } else {  //  Second and subsequently mbufs in the chain are all Data Bytes
+            //  Write the Data Bytes.
+            internal_spi_noblock_write(data, false) ? ;
+        }
     }
+    Ok(())
 }
 
 /// Perform non-blocking SPI write in Mynewt OS.  Blocks until SPI write completes.
-fn internal_spi_noblock_write(buf: &'static u8, len: i32, is_command: bool) -> MynewtResult<()> {
-    if len == 0 { return Ok(()); }
-    assert!(len > 0, "bad spi len");
+fn internal_spi_noblock_write(buf: &[u8], is_command: bool) -> MynewtResult<()> {
+    if buf.is_empty() { return Ok(()); }
+    let len = buf.len() as i32;
 
     //  If this is a Command Byte, set DC Pin to low, else set DC Pin to high.
     unsafe { hal::hal_gpio_write(
@@ -315,23 +326,31 @@ fn internal_spi_noblock_write(buf: &'static u8, len: i32, is_command: bool) -> M
         //  Write the SPI byte the blocking way.
         let rc = unsafe { hal::hal_spi_txrx(
             SPI_NUM, 
-            core::mem::transmute(buf), //  TX Buffer
+            buf.as_ptr() as Ptr,  //  TX Buffer
             NULL,     //  RX Buffer (don't receive)        
             len) };
-        assert_eq!(rc, 0, "spi fail");  //  TODO: Map to MynewtResult
+        if rc != 0 {  //  If transfer failed, set SS Pin to high to stop the transfer.
+            unsafe { hal::hal_gpio_write(SPI_SS_PIN, 1) };
+            return Err(HalError::SpiTransfer(rc).into());
+        }
 
     } else {  //  If writing more than 1 byte...
         //  Write the SPI data the non-blocking way.  Will call spi_noblock_handler() after writing.
         let rc = unsafe { hal::hal_spi_txrx_noblock(
             SPI_NUM, 
-            core::mem::transmute(buf), //  TX Buffer
+            buf.as_ptr() as Ptr,  //  TX Buffer
             NULL,     //  RX Buffer (don't receive)        
             len) };
-        assert_eq!(rc, 0, "spi fail");  //  TODO: Map to MynewtResult
+        if rc != 0 {  //  If transfer failed, set SS Pin to high to stop the transfer.
+            unsafe { hal::hal_gpio_write(SPI_SS_PIN, 1) };
+            return Err(HalError::SpiTransfer(rc).into());
+        }
 
         //  Wait for spi_noblock_handler() to signal that SPI request has been completed. Timeout in 30 seconds.
-        let timeout = 30_000;
-        unsafe { os::os_sem_pend(&mut SPI_SEM, timeout * OS_TICKS_PER_SEC / 1000) };
+        if let Err(err) = SPI_SEM.pend(SPI_TIMEOUT) {  //  If timeout, set SS Pin to high to stop the transfer.
+            unsafe { hal::hal_gpio_write(SPI_SS_PIN, 1) };
+            return Err(err);
+        }
     }
 
     //  Set SS Pin to high to stop the transfer.
@@ -342,20 +361,7 @@ fn internal_spi_noblock_write(buf: &'static u8, len: i32, is_command: bool) -> M
 /// Called by interrupt handler after Non-blocking SPI transfer has completed
 extern "C" fn spi_noblock_handler(_arg: Ptr, _len: i32) {
     //  Signal to internal_spi_noblock_write() that SPI request has been completed.
-    let rc = unsafe { os::os_sem_release(&mut SPI_SEM) };
-    assert_eq!(rc, 0, "sem fail");
-}
-
-/// Sleep for the specified number of milliseconds
-fn delay_ms(ms: u8) {
-    let delay_ticks = (ms as u32) * OS_TICKS_PER_SEC / 1000;
-    unsafe { os::os_time_delay(delay_ticks) };
-}
-
-//  TODO: Move this to Mynewt library
-extern "C" { 
-    /// Tickles the watchdog so that the Watchdog Timer doesn't expire. This needs to be done periodically, before the value configured in hal_watchdog_init() expires.
-    fn hal_watchdog_tickle(); 
+    SPI_SEM.release().expect("sem fail");
 }
 
 /* Original mbuf code in C
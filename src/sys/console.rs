@@ -74,6 +74,19 @@ pub fn flush() {
     unsafe { console_flush(); }
 }
 
+///  Formatted output to the console, e.g. `write!(console::Console, "{}", err)`.
+///  Messages are added to the output buffer, call `flush()` to display them.
+pub struct Console;
+
+///  Formatted output to the console
+impl core::fmt::Write for Console {
+    ///  Add the string to the output buffer.
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        buffer(s);
+        Ok(())
+    }
+}
+
 ///  Import the custom Mynewt library for displaying messages on the Arm Semihosting Console (via OpenOCD).
 ///  The library is located at `libs/semihosting_console`
 #[link(name = "libs_semihosting_console")]  //  Functions below are located in the Mynewt build output `libs_semihosting_console.a`
@@ -2,3 +2,5 @@
 
 #[macro_use]   //  Allow macros from Rust module `util/macros.rs`
 pub mod macros;  //  Export macros from `util/macros.rs`
+
+pub mod zeroable;  //  Export `util/zeroable.rs`
@@ -6,44 +6,16 @@
 //  Utility Macros
 
 ///  Return a const struct that has all fields set to 0. Used for initialising static mutable structs like `os_task`.
+///  The struct must implement `Zeroable`, so structs that contain references or non-nullable function pointers
+///  will fail to compile. New code should call `util::zeroable::zeroed()` instead.
 ///  `fill_zero!(os::os_task)` expands to
 ///  ```
-/// unsafe { 
-///	::core::mem::transmute::
-///	<
-///	  [
-///		u8; 
-///		::core::mem::size_of::<os::os_task>()
-///	  ], 
-///	  os::os_task
-///	>
-///	(
-///	  [
-///		0; 
-///		::core::mem::size_of::<os::os_task>()
-///	  ]
-///	) 
-/// }
+///  $crate::util::zeroable::zeroed::<os::os_task>()
 ///  ```
 #[macro_export]
 macro_rules! fill_zero {
   ($($tts:tt)*) => {
-    unsafe { 
-      ::core::mem::transmute::
-      <
-      [
-          u8; 
-          ::core::mem::size_of::<$($tts)*>()
-      ], 
-      $($tts)*
-      >
-      (
-      [
-          0; 
-          ::core::mem::size_of::<$($tts)*>()
-      ]
-      ) 
-    }      
+    $crate::util::zeroable::zeroed::<$($tts)*>()
   };
 }
 
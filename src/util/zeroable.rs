@@ -0,0 +1,124 @@
+//! Safe zero-initialisation for structs that are valid when all bytes are 0, like the bindgen structs
+//! `os_task`, `os_sem` and `CborEncoder`. Used for initialising static mutable structs:
+//! ```
+//! static mut SPI_SEM: os::os_sem = zeroed();
+//! ```
+//! Zero-initialising a type that doesn't implement `Zeroable` fails to compile. References and non-nullable
+//! function pointers (`fn()` instead of `Option<fn()>`) are never zero-valid, so structs that contain them
+//! must not implement `Zeroable`.
+
+use crate::{
+    encoding::tinycbor,
+    hw::hal,
+    kernel::os,
+};
+
+/// Marker trait for types that are valid when all bytes are 0
+///
+/// # Safety
+///
+/// Implement only for types whose fields are all zero-valid: integers, floats, `bool`, raw pointers,
+/// `Option` of function pointers and references, arrays of zero-valid types and structs that implement `Zeroable`.
+/// Types that contain references, non-nullable function pointers, `NonNull` or enums without a 0 discriminant
+/// must not implement `Zeroable`.
+pub unsafe trait Zeroable: Sized {
+    /// Value of this type with all bytes set to 0
+    const ZEROED: Self = unsafe { core::mem::zeroed() };
+}
+
+/// Return a value of type `T` with all bytes set to 0. May be used in `const` and `static` initialisers.
+/// `zeroed::<os::os_task>()` is equivalent to the C declaration `static struct os_task task;`
+pub const fn zeroed<T: Zeroable>() -> T {
+    T::ZEROED
+}
+
+/// Implement `Zeroable` for each type. The caller must check that every field of the type is zero-valid.
+macro_rules! unsafe_impl_zeroable {
+    ($($t:ty),* $(,)?) => {
+        $( unsafe impl Zeroable for $t {} )*
+    };
+}
+
+unsafe_impl_zeroable!(
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    f32, f64, bool, char, (),
+);
+
+unsafe impl<T> Zeroable for *const T {}
+unsafe impl<T> Zeroable for *mut T {}
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+unsafe impl<T> Zeroable for core::marker::PhantomData<T> {}
+unsafe impl<T> Zeroable for Option<&T> {}
+unsafe impl<T> Zeroable for Option<&mut T> {}
+
+//  Mynewt OS structs. Checked against `kernel/os.rs`: all fields are integers, `bool`, raw pointers,
+//  arrays or `Option` of function pointers.
+unsafe_impl_zeroable!(
+    os::os_timeval,
+    os::os_timezone,
+    os::os_time_change_info,
+    os::os_time_change_listener,
+    os::os_time_change_listener__bindgen_ty_1,
+    os::os_event,
+    os::os_event__bindgen_ty_1,
+    os::os_eventq,
+    os::os_eventq__bindgen_ty_1,
+    os::os_callout,
+    os::os_callout__bindgen_ty_1,
+    os::os_callout_list,
+    os::hal_timer,
+    os::hal_timer__bindgen_ty_1,
+    os::os_dev_handlers,
+    os::os_dev,
+    os::os_dev__bindgen_ty_1,
+    os::os_mbuf_pool,
+    os::os_mbuf_pool__bindgen_ty_1,
+    os::os_mbuf_pkthdr,
+    os::os_mbuf_pkthdr__bindgen_ty_1,
+    os::os_mbuf,
+    os::os_mbuf__bindgen_ty_1,
+    os::os_mqueue,
+    os::os_mqueue__bindgen_ty_1,
+    os::os_memblock,
+    os::os_memblock__bindgen_ty_1,
+    os::os_mempool,
+    os::os_mempool__bindgen_ty_1,
+    os::os_mempool__bindgen_ty_2,
+    os::os_mempool_ext,
+    os::os_mempool_info,
+    os::os_mutex,
+    os::os_mutex__bindgen_ty_1,
+    os::os_sanity_check,
+    os::os_sanity_check__bindgen_ty_1,
+    os::os_task_obj,
+    os::os_task_obj__bindgen_ty_1,
+    os::os_task,
+    os::os_task__bindgen_ty_1,
+    os::os_task__bindgen_ty_2,
+    os::os_task__bindgen_ty_3,
+    os::os_task_stailq,
+    os::os_task_info,
+    os::os_task_list,
+    os::os_sem,
+    os::os_sem__bindgen_ty_1,
+);
+
+//  Mynewt HAL structs
+unsafe_impl_zeroable!(
+    hal::hal_i2c_hw_settings,
+    hal::hal_i2c_settings,
+    hal::hal_i2c_master_data,
+    hal::hal_spi_hw_settings,
+    hal::hal_spi_settings,
+);
+
+//  TinyCBOR structs
+unsafe_impl_zeroable!(
+    tinycbor::cbor_encoder_writer,
+    tinycbor::cbor_iovec,
+    tinycbor::CborEncoder,
+    tinycbor::cbor_decoder_reader,
+    tinycbor::CborParser,
+    tinycbor::CborValue,
+);